@@ -0,0 +1,481 @@
+use alloc::vec::Vec;
+use core::{mem, ptr, slice};
+use x86_64::{PhysAddr, VirtAddr};
+
+/// Parsed ACPI tables, available after `init`
+static TABLES: spin::Once<AcpiTables> = spin::Once::new();
+
+/// Signature at the start of the RSDP
+const RSDP_SIGNATURE: &[u8; 8] = b"RSD PTR ";
+/// BIOS data area word holding the segment of the extended BIOS data area
+const EBDA_SEGMENT_POINTER: u64 = 0x40E;
+/// The main BIOS area, which may hold the RSDP
+const BIOS_AREA: (u64, u64) = (0xE_0000, 0x10_0000);
+
+/// Errors that can happen while reading the ACPI tables
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AcpiError {
+    /// The RSDP is neither in the EBDA nor in the BIOS area
+    RsdpNotFound,
+    /// The table with this signature has an invalid checksum
+    InvalidChecksum([u8; 4]),
+    /// The root table doesn't have the expected signature
+    InvalidRootTable([u8; 4]),
+    /// The table with this signature is too short for its fields
+    TruncatedTable([u8; 4]),
+}
+
+/// Root System Description Pointer, in the ACPI 2.0 layout
+#[allow(dead_code)]
+#[derive(Clone, Copy)]
+#[repr(C, packed)]
+struct Rsdp {
+    signature: [u8; 8],
+    checksum: u8,
+    oem_id: [u8; 6],
+    revision: u8,
+    rsdt_address: u32,
+    // Only valid from revision 2
+    length: u32,
+    xsdt_address: u64,
+    extended_checksum: u8,
+    reserved: [u8; 3],
+}
+
+/// Size of the RSDP before ACPI 2.0 added the XSDT
+const RSDP_V1_SIZE: usize = 20;
+/// Size of the HPET table, up to its last field
+const HPET_SIZE: usize = 56;
+
+/// Header shared by all the system description tables
+#[allow(dead_code)]
+#[derive(Clone, Copy)]
+#[repr(C, packed)]
+struct SdtHeader {
+    signature: [u8; 4],
+    length: u32,
+    revision: u8,
+    checksum: u8,
+    oem_id: [u8; 6],
+    oem_table_id: [u8; 8],
+    oem_revision: u32,
+    creator_id: u32,
+    creator_revision: u32,
+}
+
+/// Everything the kernel needs to know from the ACPI tables
+#[derive(Debug, Clone)]
+pub struct AcpiTables {
+    /// ACPI revision from the RSDP, 0 means ACPI 1.0
+    pub revision: u8,
+    /// Multiple APIC Description Table
+    pub madt: Option<Madt>,
+    /// Fixed ACPI Description Table
+    pub fadt: Option<Fadt>,
+    /// High Precision Event Timer Table
+    pub hpet: Option<Hpet>,
+}
+
+/// The interrupt controllers and CPUs of the machine
+#[derive(Debug, Clone, Default)]
+pub struct Madt {
+    /// Physical address of the local APIC of every CPU
+    pub local_apic_address: u64,
+    /// The machine also has the legacy 8259 PICs
+    pub has_legacy_pics: bool,
+    /// The processors with their local APIC ids
+    pub processors: Vec<Processor>,
+    /// All the IOAPICs
+    pub io_apics: Vec<IoApic>,
+    /// ISA IRQs that are not identity mapped to global system interrupts
+    pub interrupt_overrides: Vec<InterruptOverride>,
+}
+
+/// A processor with its local APIC
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Processor {
+    /// The ACPI processor id
+    pub processor_id: u8,
+    /// Id of the local APIC of the processor
+    pub apic_id: u8,
+    /// The processor is enabled, or can be enabled
+    pub usable: bool,
+}
+
+/// An IOAPIC and the interrupts it handles
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IoApic {
+    /// Id of the IOAPIC
+    pub id: u8,
+    /// Physical address of its registers
+    pub address: u64,
+    /// Global system interrupt of its first pin
+    pub gsi_base: u32,
+}
+
+/// An ISA IRQ connected to a different global system interrupt
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InterruptOverride {
+    /// The ISA IRQ line
+    pub source: u8,
+    /// The global system interrupt it is connected to
+    pub gsi: u32,
+    /// The line signals with low voltage instead of high
+    pub active_low: bool,
+    /// The line is level triggered instead of edge triggered
+    pub level_triggered: bool,
+}
+
+/// Power management details from the FADT
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Fadt {
+    /// Interrupt the ACPI events arrive on
+    pub sci_interrupt: u16,
+    /// Port for the commands enabling and disabling ACPI
+    pub smi_command_port: u32,
+    /// Value to write to `smi_command_port` to enable ACPI
+    pub acpi_enable: u8,
+    /// Value to write to `smi_command_port` to disable ACPI
+    pub acpi_disable: u8,
+    /// Port of the PM1a event register block
+    pub pm1a_event_block: u32,
+    /// Port of the PM1b event register block, 0 if not present
+    pub pm1b_event_block: u32,
+    /// Port of the PM1a control register block
+    pub pm1a_control_block: u32,
+    /// Port of the PM1b control register block, 0 if not present
+    pub pm1b_control_block: u32,
+    /// Port of the power management timer
+    pub pm_timer_block: u32,
+    /// CMOS register holding the century, 0 if not present
+    pub century_register: u8,
+    /// Register to write `reset_value` to in order to reset the machine
+    pub reset_register: Option<GenericAddress>,
+    /// Value resetting the machine
+    pub reset_value: u8,
+}
+
+/// Address of a register in one of the ACPI address spaces
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GenericAddress {
+    /// 0 for memory, 1 for I/O ports, 2 for PCI configuration space
+    pub address_space: u8,
+    /// Width of the register in bits
+    pub bit_width: u8,
+    /// Offset of the register in bits
+    pub bit_offset: u8,
+    /// The address in the address space
+    pub address: u64,
+}
+
+/// Details of the High Precision Event Timer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Hpet {
+    /// Hardware id of the timer block
+    pub event_timer_block_id: u32,
+    /// Physical address of the timer registers
+    pub address: u64,
+    /// Number of this HPET in the system
+    pub number: u8,
+    /// Minimal tick in periodic mode
+    pub minimum_tick: u16,
+}
+
+/// Finds and parses the ACPI tables through the physical memory mapping
+///
+/// # Errors
+///
+/// Returns an error if there is no RSDP or a checksum doesn't match.
+///
+/// # Safety
+///
+/// The caller must guarantee that the complete physical memory is mapped
+/// to virtual memory at the `physical_memory_offset`.
+pub unsafe fn init(physical_memory_offset: VirtAddr) -> Result<&'static AcpiTables, AcpiError> {
+    if let Some(tables) = tables() {
+        return Ok(tables);
+    }
+
+    let reader = PhysReader {
+        physical_memory_offset,
+    };
+    let tables = unsafe { reader.parse() }?;
+    Ok(TABLES.call_once(|| tables))
+}
+
+/// Returns the ACPI tables if they were already parsed by `init`
+#[must_use]
+pub fn tables() -> Option<&'static AcpiTables> {
+    TABLES.r#try()
+}
+
+/// Returns the physical address of the IOAPIC handling the ISA IRQs
+#[must_use]
+pub fn isa_io_apic_address() -> Option<PhysAddr> {
+    let madt = tables()?.madt.as_ref()?;
+    madt.io_apics
+        .iter()
+        .find(|io_apic| io_apic.gsi_base == 0)
+        .map(|io_apic| PhysAddr::new(io_apic.address))
+}
+
+/// Reads physical memory through the bootloader's mapping
+struct PhysReader {
+    physical_memory_offset: VirtAddr,
+}
+
+impl PhysReader {
+    /// Reads a `T` from the physical `address`
+    unsafe fn read<T: Copy>(&self, address: u64) -> T {
+        let virt = self.physical_memory_offset + address;
+        unsafe { ptr::read_unaligned(virt.as_ptr::<T>()) }
+    }
+
+    /// Returns `length` bytes starting at the physical `address`
+    unsafe fn bytes(&self, address: u64, length: usize) -> &'static [u8] {
+        let virt = self.physical_memory_offset + address;
+        unsafe { slice::from_raw_parts(virt.as_ptr::<u8>(), length) }
+    }
+
+    unsafe fn parse(&self) -> Result<AcpiTables, AcpiError> {
+        let rsdp_address = unsafe { self.find_rsdp() }.ok_or(AcpiError::RsdpNotFound)?;
+        let rsdp: Rsdp = unsafe { self.read(rsdp_address) };
+
+        let mut tables = AcpiTables {
+            revision: rsdp.revision,
+            madt: None,
+            fadt: None,
+            hpet: None,
+        };
+
+        for table in unsafe { self.root_entries(&rsdp) }? {
+            let header: SdtHeader = unsafe { self.read(table) };
+            let signature = header.signature;
+            match &signature {
+                b"APIC" => tables.madt = Some(unsafe { self.parse_madt(table) }?),
+                b"FACP" => tables.fadt = Some(unsafe { self.parse_fadt(table) }?),
+                b"HPET" => tables.hpet = Some(unsafe { self.parse_hpet(table) }?),
+                _ => {}
+            }
+        }
+
+        Ok(tables)
+    }
+
+    /// Scans the first KiB of the EBDA and the BIOS area for a valid RSDP
+    unsafe fn find_rsdp(&self) -> Option<u64> {
+        let ebda = u64::from(unsafe { self.read::<u16>(EBDA_SEGMENT_POINTER) }) << 4;
+        let candidates = (ebda..ebda + 1024)
+            .step_by(16)
+            .chain((BIOS_AREA.0..BIOS_AREA.1).step_by(16));
+
+        for address in candidates {
+            let signature: [u8; 8] = unsafe { self.read(address) };
+            if &signature != RSDP_SIGNATURE {
+                continue;
+            }
+
+            let rsdp: Rsdp = unsafe { self.read(address) };
+            let valid = if rsdp.revision < 2 {
+                checksum(unsafe { self.bytes(address, RSDP_V1_SIZE) })
+            } else {
+                checksum(unsafe { self.bytes(address, rsdp.length as usize) })
+            };
+            if valid {
+                return Some(address);
+            }
+        }
+
+        None
+    }
+
+    /// Returns the physical addresses of the tables listed in the XSDT, or the RSDT before ACPI 2.0
+    unsafe fn root_entries(&self, rsdp: &Rsdp) -> Result<Vec<u64>, AcpiError> {
+        let (address, signature, entry_size) = if rsdp.revision >= 2 && rsdp.xsdt_address != 0 {
+            (rsdp.xsdt_address, b"XSDT", mem::size_of::<u64>())
+        } else {
+            (u64::from(rsdp.rsdt_address), b"RSDT", mem::size_of::<u32>())
+        };
+
+        let table = unsafe { self.table(address) }?;
+        if &table[..4] != signature {
+            return Err(AcpiError::InvalidRootTable(
+                table[..4].try_into().unwrap_or_default(),
+            ));
+        }
+
+        let entries = table[mem::size_of::<SdtHeader>()..]
+            .chunks_exact(entry_size)
+            .map(|entry| {
+                let mut bytes = [0; 8];
+                bytes[..entry_size].copy_from_slice(entry);
+                u64::from_le_bytes(bytes)
+            })
+            .collect();
+        Ok(entries)
+    }
+
+    /// Returns the whole table at `address` after validating its checksum
+    unsafe fn table(&self, address: u64) -> Result<&'static [u8], AcpiError> {
+        let header: SdtHeader = unsafe { self.read(address) };
+        let table = unsafe { self.bytes(address, header.length as usize) };
+        if checksum(table) {
+            Ok(table)
+        } else {
+            Err(AcpiError::InvalidChecksum(header.signature))
+        }
+    }
+
+    unsafe fn parse_madt(&self, address: u64) -> Result<Madt, AcpiError> {
+        let table = unsafe { self.table(address) }?;
+        let body = &table[mem::size_of::<SdtHeader>()..];
+
+        let mut madt = Madt {
+            local_apic_address: u64::from(read_u32(body, 0)),
+            has_legacy_pics: read_u32(body, 4) & 1 != 0,
+            ..Madt::default()
+        };
+
+        // Variable length entries follow the local APIC address and the flags
+        let mut entries = &body[8..];
+        while let [entry_type, length, ..] = *entries {
+            let length = usize::from(length);
+            if length < 2 || length > entries.len() {
+                break;
+            }
+            let entry = &entries[..length];
+
+            match entry_type {
+                0 => madt.processors.push(Processor {
+                    processor_id: entry[2],
+                    apic_id: entry[3],
+                    // Enabled or online capable
+                    usable: read_u32(entry, 4) & 0b11 != 0,
+                }),
+                1 => madt.io_apics.push(IoApic {
+                    id: entry[2],
+                    address: u64::from(read_u32(entry, 4)),
+                    gsi_base: read_u32(entry, 8),
+                }),
+                2 => {
+                    let flags = read_u16(entry, 8);
+                    madt.interrupt_overrides.push(InterruptOverride {
+                        source: entry[3],
+                        gsi: read_u32(entry, 4),
+                        active_low: flags & 0b11 == 0b11,
+                        level_triggered: (flags >> 2) & 0b11 == 0b11,
+                    });
+                }
+                5 => madt.local_apic_address = read_u64(entry, 4),
+                _ => {}
+            }
+
+            entries = &entries[length..];
+        }
+
+        Ok(madt)
+    }
+
+    unsafe fn parse_fadt(&self, address: u64) -> Result<Fadt, AcpiError> {
+        let table = unsafe { self.table(address) }?;
+        let byte = |offset: usize| table.get(offset).copied().unwrap_or(0);
+
+        // The reset register was only added in ACPI 2.0
+        let reset_register = (table.len() > 128)
+            .then(|| GenericAddress {
+                address_space: table[116],
+                bit_width: table[117],
+                bit_offset: table[118],
+                address: read_u64(table, 120),
+            })
+            .filter(|register| register.address != 0);
+
+        Ok(Fadt {
+            sci_interrupt: read_u16(table, 46),
+            smi_command_port: read_u32(table, 48),
+            acpi_enable: byte(52),
+            acpi_disable: byte(53),
+            pm1a_event_block: read_u32(table, 56),
+            pm1b_event_block: read_u32(table, 60),
+            pm1a_control_block: read_u32(table, 64),
+            pm1b_control_block: read_u32(table, 68),
+            pm_timer_block: read_u32(table, 76),
+            century_register: byte(108),
+            reset_register,
+            reset_value: byte(128),
+        })
+    }
+
+    unsafe fn parse_hpet(&self, address: u64) -> Result<Hpet, AcpiError> {
+        let table = unsafe { self.table(address) }?;
+        if table.len() < HPET_SIZE {
+            return Err(AcpiError::TruncatedTable(*b"HPET"));
+        }
+
+        Ok(Hpet {
+            event_timer_block_id: read_u32(table, 36),
+            address: read_u64(table, 44),
+            number: read_u8(table, 52),
+            minimum_tick: read_u16(table, 53),
+        })
+    }
+}
+
+/// All bytes of a valid table sum up to zero
+fn checksum(bytes: &[u8]) -> bool {
+    bytes.iter().fold(0u8, |sum, &byte| sum.wrapping_add(byte)) == 0
+}
+
+fn read_u8(bytes: &[u8], offset: usize) -> u8 {
+    bytes.get(offset).copied().unwrap_or(0)
+}
+
+fn read_u16(bytes: &[u8], offset: usize) -> u16 {
+    bytes
+        .get(offset..offset + 2)
+        .and_then(|bytes| bytes.try_into().ok())
+        .map_or(0, u16::from_le_bytes)
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    bytes
+        .get(offset..offset + 4)
+        .and_then(|bytes| bytes.try_into().ok())
+        .map_or(0, u32::from_le_bytes)
+}
+
+fn read_u64(bytes: &[u8], offset: usize) -> u64 {
+    bytes
+        .get(offset..offset + 8)
+        .and_then(|bytes| bytes.try_into().ok())
+        .map_or(0, u64::from_le_bytes)
+}
+
+#[test_case]
+fn test_checksum() {
+    assert!(checksum(&[0x01, 0xFF]));
+    assert!(checksum(&[]));
+    assert!(!checksum(&[0x01, 0x02]));
+}
+
+#[test_case]
+fn test_read_out_of_bounds() {
+    assert_eq!(read_u32(&[1, 0, 0, 0], 0), 1);
+    assert_eq!(read_u32(&[1, 0, 0], 0), 0);
+}
+
+#[test_case]
+fn test_truncated_hpet() {
+    // Ends right before the comparator number
+    let mut table = [0u8; 52];
+    table[..4].copy_from_slice(b"HPET");
+    table[4..8].copy_from_slice(&52u32.to_le_bytes());
+    table[9] = table.iter().fold(0u8, |sum, &byte| sum.wrapping_sub(byte));
+
+    // Without an offset the reader takes the virtual address of the table
+    let reader = PhysReader {
+        physical_memory_offset: VirtAddr::zero(),
+    };
+    let result = unsafe { reader.parse_hpet(table.as_ptr() as u64) };
+    assert_eq!(result.err(), Some(AcpiError::TruncatedTable(*b"HPET")));
+}
@@ -0,0 +1,167 @@
+use core::fmt;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use spin::Mutex;
+use x86_64::instructions::interrupts::without_interrupts;
+
+/// Size of the kernel log in bytes, older output is overwritten
+pub const DMESG_SIZE: usize = 16 * 1024;
+/// How much `dump` copies out of the log at once
+const DUMP_CHUNK: usize = 256;
+
+/// The kernel log
+///
+/// It is only locked with interrupts disabled and never while printing, so it can be
+/// written from interrupt handlers no matter who holds the screen or serial locks.
+static LOG: Mutex<Ring> = Mutex::new(Ring::new());
+/// Writes lost because the log was locked, which happens when an NMI or an exception
+/// interrupts a write
+static DROPPED_WRITES: AtomicUsize = AtomicUsize::new(0);
+
+struct Ring {
+    bytes: [u8; DMESG_SIZE],
+    /// Bytes written since boot, the next one goes to `written % DMESG_SIZE`
+    written: u64,
+}
+
+impl Ring {
+    const fn new() -> Self {
+        Self {
+            bytes: [0; DMESG_SIZE],
+            written: 0,
+        }
+    }
+
+    /// Position of the oldest byte still in the ring
+    const fn oldest(&self) -> u64 {
+        self.written.saturating_sub(DMESG_SIZE as u64)
+    }
+
+    fn push(&mut self, data: &[u8]) {
+        for &byte in data {
+            self.bytes[(self.written % DMESG_SIZE as u64) as usize] = byte;
+            self.written += 1;
+        }
+    }
+
+    /// Copies the bytes from `position` on into `out` and returns how many there were
+    fn copy_from(&self, position: u64, out: &mut [u8]) -> usize {
+        let len = out.len().min((self.written - position) as usize);
+        for (offset, byte) in out[..len].iter_mut().enumerate() {
+            *byte = self.bytes[((position + offset as u64) % DMESG_SIZE as u64) as usize];
+        }
+        len
+    }
+}
+
+impl fmt::Write for Ring {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.push(s.as_bytes());
+        Ok(())
+    }
+}
+
+/// Appends the formatted `args` to the kernel log, called by the print macros and the logger
+pub(crate) fn write_fmt(args: fmt::Arguments) {
+    use core::fmt::Write;
+
+    without_interrupts(|| {
+        // Whoever holds the lock was interrupted by us, waiting would never end
+        let Some(mut log) = LOG.try_lock() else {
+            DROPPED_WRITES.fetch_add(1, Ordering::Relaxed);
+            return;
+        };
+        let _ = log.write_fmt(args);
+    });
+}
+
+/// Number of writes lost because they interrupted another write to the log
+#[must_use]
+pub fn dropped_writes() -> usize {
+    DROPPED_WRITES.load(Ordering::Relaxed)
+}
+
+/// Number of bytes written to the log since boot, including the overwritten ones
+#[must_use]
+pub fn written() -> u64 {
+    without_interrupts(|| LOG.lock().written)
+}
+
+/// Releases the log no matter who holds it
+///
+/// # Safety
+///
+/// The holder must never use the log again, like the code interrupted by a panic.
+pub unsafe fn force_unlock() {
+    unsafe { LOG.force_unlock() };
+}
+
+/// Passes the contents of the log to `write`, oldest first
+///
+/// The log is copied out in small chunks and unlocked while `write` runs, so `write`
+/// may print with the macros that write to the log themselves. Whatever they add
+/// is not part of the dump.
+pub fn dump(mut write: impl FnMut(&str)) {
+    let end = written();
+    let mut position = 0;
+    let mut chunk = [0; DUMP_CHUNK];
+
+    while position < end {
+        let len = without_interrupts(|| {
+            let log = LOG.lock();
+            // Skip what was overwritten in the meantime
+            position = position.max(log.oldest());
+            let len = (end.saturating_sub(position) as usize).min(DUMP_CHUNK);
+            log.copy_from(position, &mut chunk[..len])
+        });
+        if len == 0 {
+            break;
+        }
+
+        let (text, consumed, invalid) = match core::str::from_utf8(&chunk[..len]) {
+            Ok(text) => (text, len, false),
+            Err(error) => {
+                let valid = error.valid_up_to();
+                let text = core::str::from_utf8(&chunk[..valid]).unwrap_or_default();
+                match error.error_len() {
+                    // Cut in half by the end of the chunk, the next one starts with it
+                    None if valid > 0 => (text, valid, false),
+                    // Cut off by the start of the ring
+                    error_len => (text, valid + error_len.unwrap_or(len - valid), true),
+                }
+            }
+        };
+        write(text);
+        if invalid {
+            write("\u{FFFD}");
+        }
+        position += consumed as u64;
+    }
+}
+
+/// Dumps the log to the serial, used when the kernel panics
+pub fn dump_to_serial() {
+    crate::serial::write_fmt(format_args!("--- dmesg ---\n"));
+    dump(|text| crate::serial::write_fmt(format_args!("{text}")));
+    crate::serial::write_fmt(format_args!("--- end of dmesg ---\n"));
+}
+
+#[test_case]
+fn test_ring_keeps_the_newest_bytes() {
+    let mut ring = Ring::new();
+    ring.push(&[b'a'; DMESG_SIZE]);
+    ring.push(b"bc");
+    assert_eq!(ring.oldest(), 2);
+
+    let mut out = [0; 4];
+    let len = ring.copy_from(ring.written - 4, &mut out);
+    assert_eq!(&out[..len], b"aabc");
+}
+
+#[test_case]
+fn test_print_is_captured() {
+    crate::serial_print!("test_print_is_captured marker ");
+
+    let mut contents = alloc::string::String::new();
+    dump(|text| contents.push_str(text));
+    assert!(contents.contains("test_print_is_captured marker"));
+}
@@ -0,0 +1,318 @@
+use crate::{graphics::Canvas, memory, pci};
+use x86_64::instructions::port::Port;
+use x86_64::structures::paging::{mapper::MapToError, FrameAllocator, Mapper, Size4KiB};
+use x86_64::PhysAddr;
+
+/// Text console drawn with a bitmap font
+pub mod console;
+/// PSF bitmap fonts
+pub mod font;
+
+/// Index and data ports of the Bochs graphics adapter, emulated by QEMU's standard VGA
+const BGA_INDEX: u16 = 0x01CE;
+const BGA_DATA: u16 = 0x01CF;
+/// BGA registers
+const BGA_ID: u16 = 0;
+const BGA_XRES: u16 = 1;
+const BGA_YRES: u16 = 2;
+const BGA_BPP: u16 = 3;
+const BGA_ENABLE: u16 = 4;
+const BGA_VIRT_WIDTH: u16 = 6;
+const BGA_VIRT_HEIGHT: u16 = 7;
+const BGA_Y_OFFSET: u16 = 9;
+/// The versions of the adapter all start with this ID
+const BGA_ID_MIN: u16 = 0xB0C0;
+const BGA_ID_MAX: u16 = 0xB0C5;
+/// Bits of `BGA_ENABLE`
+const BGA_ENABLED: u16 = 0x01;
+const BGA_LFB_ENABLED: u16 = 0x40;
+/// Biggest mode the adapter supports
+const BGA_MAX_WIDTH: usize = 2560;
+const BGA_MAX_HEIGHT: usize = 1600;
+/// Only 32 bits per pixel are supported, as `0x00RRGGBB`
+const BITS_PER_PIXEL: u16 = 32;
+
+/// PCI IDs of QEMU's standard VGA, its BAR 0 is the linear framebuffer
+const QEMU_VGA_VENDOR: u16 = 0x1234;
+const QEMU_VGA_DEVICE: u16 = 0x1111;
+
+/// Number of pages `init` asks for, the second one is for page flipping
+const PAGES: usize = 2;
+
+/// The framebuffer set up by `init`, the first page
+static FRAMEBUFFER: spin::Once<Framebuffer> = spin::Once::new();
+/// The page after it, if the adapter has enough memory
+static SECOND_PAGE: spin::Once<Framebuffer> = spin::Once::new();
+
+/// A color of a pixel
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Rgb {
+    pub red: u8,
+    pub green: u8,
+    pub blue: u8,
+}
+
+impl Rgb {
+    pub const BLACK: Self = Self::new(0, 0, 0);
+    pub const WHITE: Self = Self::new(0xff, 0xff, 0xff);
+
+    #[must_use]
+    pub const fn new(red: u8, green: u8, blue: u8) -> Self {
+        Self { red, green, blue }
+    }
+
+    /// The value of the pixel in the framebuffer
+    const fn to_pixel(self) -> u32 {
+        (self.red as u32) << 16 | (self.green as u32) << 8 | self.blue as u32
+    }
+
+    const fn from_pixel(pixel: u32) -> Self {
+        Self::new((pixel >> 16) as u8, (pixel >> 8) as u8, pixel as u8)
+    }
+}
+
+/// The 16 colors of the VGA text mode, indexed like `vga_buffer::Color`
+pub const PALETTE: [Rgb; 16] = [
+    Rgb::new(0x00, 0x00, 0x00),
+    Rgb::new(0x00, 0x00, 0xaa),
+    Rgb::new(0x00, 0xaa, 0x00),
+    Rgb::new(0x00, 0xaa, 0xaa),
+    Rgb::new(0xaa, 0x00, 0x00),
+    Rgb::new(0xaa, 0x00, 0xaa),
+    Rgb::new(0xaa, 0x55, 0x00),
+    Rgb::new(0xaa, 0xaa, 0xaa),
+    Rgb::new(0x55, 0x55, 0x55),
+    Rgb::new(0x55, 0x55, 0xff),
+    Rgb::new(0x55, 0xff, 0x55),
+    Rgb::new(0x55, 0xff, 0xff),
+    Rgb::new(0xff, 0x55, 0x55),
+    Rgb::new(0xff, 0x55, 0xff),
+    Rgb::new(0xff, 0xff, 0x55),
+    Rgb::new(0xff, 0xff, 0xff),
+];
+
+/// Errors returned by `init`
+#[derive(Debug)]
+pub enum FramebufferError {
+    /// There is no Bochs graphics adapter
+    NotPresent,
+    /// The adapter can't show the requested resolution
+    UnsupportedMode { width: usize, height: usize },
+    /// The adapter isn't on the PCI bus, so the address of the framebuffer is unknown
+    NoLinearFramebuffer,
+    /// The framebuffer could not be mapped
+    Map(MapToError<Size4KiB>),
+}
+
+/// A linear framebuffer with 32 bits per pixel
+///
+/// It's only a handle, copies draw to the same pixels. Drawing outside of it does nothing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Framebuffer {
+    /// Virtual address of the first pixel
+    base: usize,
+    width: usize,
+    height: usize,
+    /// Pixels from the start of one line to the next
+    stride: usize,
+}
+
+impl Framebuffer {
+    #[must_use]
+    pub const fn width(&self) -> usize {
+        self.width
+    }
+
+    #[must_use]
+    pub const fn height(&self) -> usize {
+        self.height
+    }
+
+    fn pixel_pointer(&self, x: usize, y: usize) -> Option<*mut u32> {
+        (x < self.width && y < self.height)
+            .then(|| (self.base as *mut u32).wrapping_add(y * self.stride + x))
+    }
+
+    /// Sets the pixel at `x`, `y` to `color`
+    pub fn set_pixel(&self, x: usize, y: usize, color: Rgb) {
+        if let Some(pixel) = self.pixel_pointer(x, y) {
+            unsafe { pixel.write_volatile(color.to_pixel()) };
+        }
+    }
+
+    /// Returns the color of the pixel at `x`, `y`
+    ///
+    /// Reading the framebuffer is slow, keep a copy of what was drawn instead where possible.
+    #[must_use]
+    pub fn pixel(&self, x: usize, y: usize) -> Option<Rgb> {
+        self.pixel_pointer(x, y)
+            .map(|pixel| Rgb::from_pixel(unsafe { pixel.read_volatile() }))
+    }
+}
+
+impl Canvas for Framebuffer {
+    fn size(&self) -> (usize, usize) {
+        (self.width, self.height)
+    }
+
+    fn put_pixel(&mut self, x: usize, y: usize, color: Rgb) {
+        self.set_pixel(x, y, color);
+    }
+}
+
+/// Returns the framebuffer, `None` before `init`
+#[must_use]
+pub fn get() -> Option<Framebuffer> {
+    FRAMEBUFFER.r#try().copied()
+}
+
+/// Returns page `index` of the framebuffer, page 0 is the one `get` returns
+///
+/// `None` before `init` or if the adapter has no memory for the page.
+#[must_use]
+pub fn page(index: usize) -> Option<Framebuffer> {
+    match index {
+        0 => get(),
+        1 => SECOND_PAGE.r#try().copied(),
+        _ => None,
+    }
+}
+
+/// Shows page `index` on the screen
+///
+/// Does nothing if there is no such page.
+pub fn show_page(index: usize) {
+    if let Some(page) = page(index) {
+        unsafe { write_bga(BGA_Y_OFFSET, (index * page.height) as u16) };
+    }
+}
+
+/// Parses the resolution of the `video=<width>x<height>` option of the kernel command line
+#[must_use]
+pub fn mode_from_command_line(command_line: &str) -> Option<(usize, usize)> {
+    let mode = command_line
+        .split_whitespace()
+        .find_map(|option| option.strip_prefix("video="))?;
+    let (width, height) = mode.split_once('x')?;
+    Some((width.parse().ok()?, height.parse().ok()?))
+}
+
+/// Switches the Bochs graphics adapter to a `width` x `height` linear framebuffer
+/// and moves the console on the screen there
+///
+/// The console uses the font of the VGA BIOS, which has to be read while the adapter
+/// is still in text mode. Calling it again does nothing.
+///
+/// # Errors
+///
+/// Fails if there is no adapter, it doesn't support the mode or the framebuffer
+/// could not be mapped. The text mode is kept then.
+pub fn init(
+    width: usize,
+    height: usize,
+    mapper: &mut impl Mapper<Size4KiB>,
+    frame_allocator: &mut impl FrameAllocator<Size4KiB>,
+) -> Result<(), FramebufferError> {
+    if FRAMEBUFFER.r#try().is_some() {
+        return Ok(());
+    }
+
+    let id = unsafe { read_bga(BGA_ID) };
+    if !(BGA_ID_MIN..=BGA_ID_MAX).contains(&id) {
+        return Err(FramebufferError::NotPresent);
+    }
+    let unsupported = FramebufferError::UnsupportedMode { width, height };
+    if width == 0 || height == 0 || width > BGA_MAX_WIDTH || height > BGA_MAX_HEIGHT {
+        return Err(unsupported);
+    }
+    let address = pci::find_device(QEMU_VGA_VENDOR, QEMU_VGA_DEVICE)
+        .and_then(|location| location.memory_bar(0))
+        .ok_or(FramebufferError::NoLinearFramebuffer)?;
+
+    let font = font::capture_vga_font(mapper, frame_allocator).map_err(FramebufferError::Map)?;
+
+    // The registers only take the new mode while the adapter is disabled
+    unsafe {
+        write_bga(BGA_ENABLE, 0);
+        write_bga(BGA_XRES, width as u16);
+        write_bga(BGA_YRES, height as u16);
+        write_bga(BGA_BPP, BITS_PER_PIXEL);
+        write_bga(BGA_ENABLE, BGA_ENABLED | BGA_LFB_ENABLED);
+    }
+    let (actual_width, actual_height) = unsafe { (read_bga(BGA_XRES), read_bga(BGA_YRES)) };
+    if (usize::from(actual_width), usize::from(actual_height)) != (width, height) {
+        unsafe { write_bga(BGA_ENABLE, 0) };
+        return Err(unsupported);
+    }
+    let stride = usize::from(unsafe { read_bga(BGA_VIRT_WIDTH) });
+    // The pages are below each other, the adapter shrinks the height if the memory is too small
+    let pages = unsafe {
+        write_bga(BGA_VIRT_HEIGHT, (height * PAGES) as u16);
+        usize::from(read_bga(BGA_VIRT_HEIGHT)) / height
+    }
+    .clamp(1, PAGES);
+
+    let page_size = stride * height * usize::from(BITS_PER_PIXEL / 8);
+    let size = (page_size * pages) as u64;
+    let base = unsafe { memory::map_mmio(PhysAddr::new(address), size, mapper, frame_allocator) }
+        .map_err(|error| {
+        unsafe { write_bga(BGA_ENABLE, 0) };
+        FramebufferError::Map(error)
+    })?;
+
+    let framebuffer = *FRAMEBUFFER.call_once(|| Framebuffer {
+        base: base.as_u64() as usize,
+        width,
+        height,
+        stride,
+    });
+    if pages > 1 {
+        SECOND_PAGE.call_once(|| Framebuffer {
+            base: framebuffer.base + page_size,
+            ..framebuffer
+        });
+    }
+    console::init(framebuffer, font);
+    Ok(())
+}
+
+/// Reads the BGA register `register`
+///
+/// # Safety
+///
+/// There has to be a Bochs graphics adapter.
+unsafe fn read_bga(register: u16) -> u16 {
+    unsafe {
+        Port::new(BGA_INDEX).write(register);
+        Port::new(BGA_DATA).read()
+    }
+}
+
+/// Writes `value` to the BGA register `register`
+///
+/// # Safety
+///
+/// There has to be a Bochs graphics adapter and the value must be valid for the register.
+unsafe fn write_bga(register: u16, value: u16) {
+    unsafe {
+        Port::new(BGA_INDEX).write(register);
+        Port::new(BGA_DATA).write(value);
+    }
+}
+
+#[test_case]
+fn test_mode_from_command_line() {
+    assert_eq!(
+        mode_from_command_line("log=warn video=1024x768"),
+        Some((1024, 768))
+    );
+    assert_eq!(mode_from_command_line("video=1024"), None);
+    assert_eq!(mode_from_command_line("log=warn"), None);
+}
+
+#[test_case]
+fn test_pixel_colors_round_trip() {
+    let color = Rgb::new(0x12, 0x34, 0x56);
+    assert_eq!(color.to_pixel(), 0x0012_3456);
+    assert_eq!(Rgb::from_pixel(color.to_pixel()), color);
+}
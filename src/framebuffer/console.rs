@@ -0,0 +1,191 @@
+use super::{font::Font, Framebuffer, Rgb, PALETTE};
+use crate::graphics::Canvas;
+use crate::vga_buffer::{cp437, ScreenChar, TextBuffer, MAX_COLUMNS, MAX_ROWS};
+use alloc::boxed::Box;
+
+/// Pixel rows of the underline the cursor is drawn as
+const CURSOR_HEIGHT: usize = 2;
+
+/// The console on the screen, drawn into the framebuffer with a bitmap font
+///
+/// Keeps a copy of the cells, so it never has to read the slow framebuffer.
+struct FramebufferConsole {
+    framebuffer: Framebuffer,
+    font: Font<'static>,
+    /// The glyph of every byte of code page 437
+    glyphs: [Option<usize>; 256],
+    cells: [[ScreenChar; MAX_COLUMNS]; MAX_ROWS],
+    rows: usize,
+    cols: usize,
+    cursor: Option<(usize, usize)>,
+}
+
+impl FramebufferConsole {
+    fn new(framebuffer: Framebuffer, font: Font<'static>) -> Self {
+        let mut glyphs = [None; 256];
+        for (byte, glyph) in (0..=u8::MAX).zip(glyphs.iter_mut()) {
+            *glyph = font.glyph_index(cp437::decode(byte));
+        }
+        Self {
+            framebuffer,
+            font,
+            glyphs,
+            cells: [[ScreenChar::ZERO; MAX_COLUMNS]; MAX_ROWS],
+            rows: (framebuffer.height() / font.height()).min(MAX_ROWS),
+            cols: (framebuffer.width() / font.width()).min(MAX_COLUMNS),
+            cursor: None,
+        }
+    }
+
+    /// Draws the cell at `row`, `col`, with the cursor if it's there
+    fn draw(&self, row: usize, col: usize) {
+        let cell = self.cells[row][col];
+        let (foreground, background) = cell.colors();
+        let (foreground, background) = (
+            PALETTE[usize::from(foreground)],
+            PALETTE[usize::from(background)],
+        );
+        let glyph =
+            self.glyphs[usize::from(cell.byte())].and_then(|index| self.font.glyph_at(index));
+        let (width, height) = (self.font.width(), self.font.height());
+        let cursor_top = if self.cursor == Some((row, col)) {
+            height.saturating_sub(CURSOR_HEIGHT)
+        } else {
+            height
+        };
+
+        for y in 0..height {
+            for x in 0..width {
+                let set = y >= cursor_top || glyph.is_some_and(|glyph| glyph.is_set(x, y));
+                let color = if set { foreground } else { background };
+                self.framebuffer
+                    .set_pixel(col * width + x, row * height + y, color);
+            }
+        }
+    }
+}
+
+impl TextBuffer for FramebufferConsole {
+    fn size(&self) -> (usize, usize) {
+        (self.rows, self.cols)
+    }
+
+    fn read(&self, row: usize, col: usize) -> ScreenChar {
+        self.cells[row][col]
+    }
+
+    fn write(&mut self, row: usize, col: usize, screen_char: ScreenChar) {
+        if self.cells[row][col] != screen_char {
+            self.cells[row][col] = screen_char;
+            self.draw(row, col);
+        }
+    }
+
+    fn is_screen(&self) -> bool {
+        true
+    }
+
+    fn redraw(&mut self) {
+        for row in 0..self.rows {
+            for col in 0..self.cols {
+                self.draw(row, col);
+            }
+        }
+    }
+
+    fn show_cursor(&mut self, position: Option<(usize, usize)>) {
+        let old = core::mem::replace(&mut self.cursor, position);
+        if old == position {
+            return;
+        }
+        for (row, col) in old.into_iter().chain(position) {
+            if row < self.rows && col < self.cols {
+                self.draw(row, col);
+            }
+        }
+    }
+}
+
+/// Moves the console on the screen into `framebuffer`
+pub(super) fn init(mut framebuffer: Framebuffer, font: Font<'static>) {
+    let console = Box::leak(Box::new(FramebufferConsole::new(framebuffer, font)));
+    framebuffer.clear(Rgb::BLACK);
+    // Nothing shows the text mode buffer anymore
+    let _ = crate::vga_buffer::console::replace_screen(console);
+}
+
+/// A page in memory holding as many cells of `test_font` as the text mode
+#[cfg(test)]
+fn test_page() -> Framebuffer {
+    use crate::vga_buffer::{BUFFER_HEIGHT, BUFFER_WIDTH};
+
+    let (width, height) = (BUFFER_WIDTH * 8, BUFFER_HEIGHT * 2);
+    let pixels = Box::leak(alloc::vec![0u32; width * height].into_boxed_slice());
+    Framebuffer {
+        base: pixels.as_mut_ptr() as usize,
+        width,
+        height,
+        stride: width,
+    }
+}
+
+/// A PSF1 font with glyphs of 8x2 pixels
+#[cfg(test)]
+fn test_font() -> Font<'static> {
+    let mut font = alloc::vec![0x36, 0x04, 0, 2];
+    font.extend((0..=u8::MAX).flat_map(|byte| [byte, !byte]));
+    Font::parse(Box::leak(font.into_boxed_slice())).expect("parsing failed")
+}
+
+#[test_case]
+fn test_console_survives_double_buffering() {
+    use crate::graphics::DoubleBuffer;
+    use crate::vga_buffer::console;
+    use alloc::vec::Vec;
+    use x86_64::instructions::interrupts::without_interrupts;
+
+    let (first, second) = (test_page(), test_page());
+    let snapshot = || -> Vec<Option<Rgb>> {
+        (0..first.height())
+            .flat_map(|y| (0..first.width()).map(move |x| first.pixel(x, y)))
+            .collect()
+    };
+
+    crate::println!("\ndrawn in the framebuffer");
+    let console = Box::leak(Box::new(FramebufferConsole::new(first, test_font())));
+    let text_mode = console::replace_screen(console);
+    let console_pixels = without_interrupts(snapshot);
+
+    let mut pages = DoubleBuffer::from_pages([first, second]);
+    pages.clear(Rgb::WHITE);
+    pages.flip();
+    // The first page is the back page now, this covers the console
+    pages.clear(Rgb::new(0xff, 0, 0));
+    assert_ne!(without_interrupts(snapshot), console_pixels);
+    drop(pages);
+
+    assert_eq!(without_interrupts(snapshot), console_pixels);
+    let _ = console::replace_screen(text_mode);
+}
+
+#[test_case]
+fn test_print_while_writer_is_locked_reaches_the_framebuffer() {
+    use crate::vga_buffer::{console, WRITER};
+    use x86_64::instructions::interrupts::without_interrupts;
+
+    let console = Box::leak(Box::new(FramebufferConsole::new(test_page(), test_font())));
+    let text_mode = console::replace_screen(console);
+
+    let text = "bypassed";
+    without_interrupts(|| {
+        // Like an NMI arriving in the middle of a print
+        let _writer = WRITER.lock();
+        crate::print!("{text}");
+    });
+
+    let console = console::replace_screen(text_mode);
+    let (rows, _) = console.size();
+    for (col, byte) in text.bytes().enumerate() {
+        assert_eq!(console.read(rows - 1, col).byte(), byte);
+    }
+}
@@ -0,0 +1,408 @@
+use crate::{memory, vga_buffer::cp437};
+use x86_64::instructions::port::Port;
+use x86_64::structures::paging::{mapper::MapToError, FrameAllocator, Mapper, Size4KiB};
+use x86_64::PhysAddr;
+
+/// Magic of version 1 fonts, always 8 pixels wide
+const PSF1_MAGIC: [u8; 2] = [0x36, 0x04];
+const PSF1_HEADER_SIZE: usize = 4;
+/// PSF1 mode bits
+const PSF1_MODE_512: u8 = 0x01;
+const PSF1_MODE_HAS_TABLE: u8 = 0x02 | 0x04;
+/// Ends the characters of a glyph and starts a sequence in the PSF1 table
+const PSF1_SEPARATOR: u16 = 0xFFFF;
+const PSF1_START_SEQUENCE: u16 = 0xFFFE;
+
+/// Magic of version 2 fonts
+const PSF2_MAGIC: [u8; 4] = [0x72, 0xb5, 0x4a, 0x86];
+const PSF2_HEADER_SIZE: usize = 32;
+const PSF2_HAS_TABLE: u32 = 0x01;
+/// Ends the characters of a glyph and starts a sequence in the PSF2 table
+const PSF2_SEPARATOR: u8 = 0xFF;
+const PSF2_START_SEQUENCE: u8 = 0xFE;
+
+/// Size of the font the VGA BIOS loads into plane 2, 256 glyphs of 8x16
+const VGA_GLYPHS: usize = 256;
+const VGA_GLYPH_HEIGHT: usize = 16;
+/// Every glyph in plane 2 takes 32 bytes, no matter how high it is
+const VGA_GLYPH_STRIDE: usize = 32;
+const VGA_FONT_SIZE: usize = PSF1_HEADER_SIZE + VGA_GLYPHS * VGA_GLYPH_HEIGHT;
+/// Physical address of the VGA memory window
+const VGA_WINDOW: u64 = 0xA0000;
+
+/// Index ports of the VGA sequencer and graphics controller, the data ports follow them
+const SEQUENCER_INDEX: u16 = 0x3C4;
+const GRAPHICS_INDEX: u16 = 0x3CE;
+
+/// The VGA font as a PSF1 font, read by `capture_vga_font`
+static VGA_FONT: spin::Once<[u8; VGA_FONT_SIZE]> = spin::Once::new();
+
+/// Errors returned by `Font::parse`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FontError {
+    /// Neither a PSF1 nor a PSF2 font
+    BadMagic,
+    /// The data ends before the glyphs do
+    Truncated,
+    UnsupportedVersion(u32),
+}
+
+/// Where the characters of the glyphs are listed
+#[derive(Debug, Clone, Copy)]
+enum UnicodeTable<'a> {
+    /// The glyphs are in code page 437 order
+    None,
+    Psf1(&'a [u8]),
+    Psf2(&'a [u8]),
+}
+
+/// A PC screen font, the format of the Linux console fonts
+#[derive(Debug, Clone, Copy)]
+pub struct Font<'a> {
+    glyphs: &'a [u8],
+    count: usize,
+    width: usize,
+    height: usize,
+    bytes_per_glyph: usize,
+    table: UnicodeTable<'a>,
+}
+
+/// The bitmap of a character
+#[derive(Debug, Clone, Copy)]
+pub struct Glyph<'a> {
+    bitmap: &'a [u8],
+    width: usize,
+    height: usize,
+}
+
+impl Glyph<'_> {
+    #[must_use]
+    pub const fn width(&self) -> usize {
+        self.width
+    }
+
+    #[must_use]
+    pub const fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Whether the pixel at `x`, `y` is in the foreground color
+    #[must_use]
+    pub fn is_set(&self, x: usize, y: usize) -> bool {
+        if x >= self.width || y >= self.height {
+            return false;
+        }
+        let bytes_per_row = self.width.div_ceil(8);
+        self.bitmap[y * bytes_per_row + x / 8] & (0x80 >> (x % 8)) != 0
+    }
+}
+
+impl<'a> Font<'a> {
+    /// Parses a PSF1 or PSF2 font
+    ///
+    /// # Errors
+    ///
+    /// Fails if `data` isn't a complete font.
+    pub fn parse(data: &'a [u8]) -> Result<Self, FontError> {
+        if data.starts_with(&PSF1_MAGIC) {
+            Self::parse_psf1(data)
+        } else if data.starts_with(&PSF2_MAGIC) {
+            Self::parse_psf2(data)
+        } else {
+            Err(FontError::BadMagic)
+        }
+    }
+
+    fn parse_psf1(data: &'a [u8]) -> Result<Self, FontError> {
+        let header = data.get(..PSF1_HEADER_SIZE).ok_or(FontError::Truncated)?;
+        let (mode, height) = (header[2], usize::from(header[3]));
+        let count = if mode & PSF1_MODE_512 != 0 { 512 } else { 256 };
+
+        let end = PSF1_HEADER_SIZE + count * height;
+        let glyphs = data
+            .get(PSF1_HEADER_SIZE..end)
+            .ok_or(FontError::Truncated)?;
+        let table = if mode & PSF1_MODE_HAS_TABLE != 0 {
+            UnicodeTable::Psf1(&data[end..])
+        } else {
+            UnicodeTable::None
+        };
+        Ok(Self {
+            glyphs,
+            count,
+            width: 8,
+            height,
+            bytes_per_glyph: height,
+            table,
+        })
+    }
+
+    fn parse_psf2(data: &'a [u8]) -> Result<Self, FontError> {
+        let header = data.get(..PSF2_HEADER_SIZE).ok_or(FontError::Truncated)?;
+        let field = |index: usize| {
+            let bytes = &header[index * 4..index * 4 + 4];
+            u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+        };
+        let (version, header_size, flags) = (field(1), field(2) as usize, field(3));
+        let (count, bytes_per_glyph) = (field(4) as usize, field(5) as usize);
+        let (height, width) = (field(6) as usize, field(7) as usize);
+        if version != 0 {
+            return Err(FontError::UnsupportedVersion(version));
+        }
+        if bytes_per_glyph < height * width.div_ceil(8) {
+            return Err(FontError::Truncated);
+        }
+
+        let end = count
+            .checked_mul(bytes_per_glyph)
+            .and_then(|size| size.checked_add(header_size))
+            .ok_or(FontError::Truncated)?;
+        let glyphs = data.get(header_size..end).ok_or(FontError::Truncated)?;
+        let table = if flags & PSF2_HAS_TABLE != 0 {
+            UnicodeTable::Psf2(&data[end..])
+        } else {
+            UnicodeTable::None
+        };
+        Ok(Self {
+            glyphs,
+            count,
+            width,
+            height,
+            bytes_per_glyph,
+            table,
+        })
+    }
+
+    /// Width of every glyph in pixels
+    #[must_use]
+    pub const fn width(&self) -> usize {
+        self.width
+    }
+
+    /// Height of every glyph in pixels
+    #[must_use]
+    pub const fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Returns the glyph at `index`
+    #[must_use]
+    pub fn glyph_at(&self, index: usize) -> Option<Glyph<'a>> {
+        (index < self.count).then(|| Glyph {
+            bitmap: &self.glyphs[index * self.bytes_per_glyph..(index + 1) * self.bytes_per_glyph],
+            width: self.width,
+            height: self.height,
+        })
+    }
+
+    /// Returns the index of the glyph of `c`, `None` if the font doesn't have one
+    ///
+    /// Searches the whole Unicode table, so callers drawing a lot should keep the result.
+    #[must_use]
+    pub fn glyph_index(&self, c: char) -> Option<usize> {
+        let index = match self.table {
+            UnicodeTable::None => cp437::encode(c).map(usize::from),
+            UnicodeTable::Psf1(table) => {
+                let mut glyph = 0;
+                let mut in_sequence = false;
+                let mut found = None;
+                for entry in table.chunks_exact(2) {
+                    match u16::from_le_bytes([entry[0], entry[1]]) {
+                        PSF1_SEPARATOR => {
+                            glyph += 1;
+                            in_sequence = false;
+                        }
+                        PSF1_START_SEQUENCE => in_sequence = true,
+                        code if !in_sequence && u32::from(code) == u32::from(c) => {
+                            found = Some(glyph);
+                            break;
+                        }
+                        _ => {}
+                    }
+                }
+                found
+            }
+            UnicodeTable::Psf2(table) => {
+                table
+                    .split(|&byte| byte == PSF2_SEPARATOR)
+                    .position(|entry| {
+                        // Only single characters, not the sequences after the start marker
+                        let characters = entry
+                            .split(|&byte| byte == PSF2_START_SEQUENCE)
+                            .next()
+                            .unwrap_or_default();
+                        core::str::from_utf8(characters)
+                            .is_ok_and(|characters| characters.contains(c))
+                    })
+            }
+        };
+        index.filter(|&index| index < self.count)
+    }
+
+    /// Returns the glyph of `c`, `None` if the font doesn't have one
+    #[must_use]
+    pub fn glyph(&self, c: char) -> Option<Glyph<'a>> {
+        self.glyph_index(c).and_then(|index| self.glyph_at(index))
+    }
+}
+
+/// Returns the font read from the VGA BIOS, `None` until the framebuffer console is set up
+#[must_use]
+pub fn builtin() -> Option<Font<'static>> {
+    VGA_FONT.r#try().and_then(|font| Font::parse(font).ok())
+}
+
+/// Reads the 8x16 font the VGA BIOS loaded into plane 2 of the VGA memory
+///
+/// Only works in text mode, before the framebuffer is enabled. The font is kept as
+/// a PSF1 font, later calls return it without reading it again.
+pub(super) fn capture_vga_font(
+    mapper: &mut impl Mapper<Size4KiB>,
+    frame_allocator: &mut impl FrameAllocator<Size4KiB>,
+) -> Result<Font<'static>, MapToError<Size4KiB>> {
+    if let Some(font) = builtin() {
+        return Ok(font);
+    }
+
+    let size = (VGA_GLYPHS * VGA_GLYPH_STRIDE) as u64;
+    let window =
+        unsafe { memory::map_mmio(PhysAddr::new(VGA_WINDOW), size, mapper, frame_allocator)? };
+
+    let font = VGA_FONT.call_once(|| {
+        let mut font = [0; VGA_FONT_SIZE];
+        font[..PSF1_HEADER_SIZE].copy_from_slice(&[
+            PSF1_MAGIC[0],
+            PSF1_MAGIC[1],
+            0,
+            VGA_GLYPH_HEIGHT as u8,
+        ]);
+
+        x86_64::instructions::interrupts::without_interrupts(|| unsafe {
+            let saved = map_plane_2();
+            let plane = window.as_ptr::<u8>();
+            for (glyph, bitmap) in font[PSF1_HEADER_SIZE..]
+                .chunks_exact_mut(VGA_GLYPH_HEIGHT)
+                .enumerate()
+            {
+                for (row, byte) in bitmap.iter_mut().enumerate() {
+                    *byte = plane.add(glyph * VGA_GLYPH_STRIDE + row).read_volatile();
+                }
+            }
+            restore_planes(saved);
+        });
+        font
+    });
+    Ok(Font::parse(font).unwrap_or_else(|_| unreachable!("the VGA font is a valid PSF1 font")))
+}
+
+/// Registers `map_plane_2` changes, in the order it saves them
+const SAVED_SEQUENCER: [u8; 2] = [0x02, 0x04];
+const SAVED_GRAPHICS: [u8; 3] = [0x04, 0x05, 0x06];
+
+/// Makes plane 2, where the font is, readable at the start of the VGA window
+///
+/// Returns the old values of the registers for `restore_planes`.
+///
+/// # Safety
+///
+/// Text mode output is garbled until `restore_planes`.
+unsafe fn map_plane_2() -> ([u8; 2], [u8; 3]) {
+    let saved = (
+        SAVED_SEQUENCER.map(|register| unsafe { read_register(SEQUENCER_INDEX, register) }),
+        SAVED_GRAPHICS.map(|register| unsafe { read_register(GRAPHICS_INDEX, register) }),
+    );
+    unsafe {
+        // Only plane 2, sequential addressing instead of odd/even
+        write_register(SEQUENCER_INDEX, 0x02, 0x04);
+        write_register(SEQUENCER_INDEX, 0x04, 0x07);
+        // Read plane 2, no odd/even, window at 0xA0000
+        write_register(GRAPHICS_INDEX, 0x04, 0x02);
+        write_register(GRAPHICS_INDEX, 0x05, 0x00);
+        write_register(GRAPHICS_INDEX, 0x06, 0x04);
+    }
+    saved
+}
+
+/// Undoes `map_plane_2`
+///
+/// # Safety
+///
+/// `saved` has to be what `map_plane_2` returned.
+unsafe fn restore_planes((sequencer, graphics): ([u8; 2], [u8; 3])) {
+    for (register, value) in SAVED_SEQUENCER.into_iter().zip(sequencer) {
+        unsafe { write_register(SEQUENCER_INDEX, register, value) };
+    }
+    for (register, value) in SAVED_GRAPHICS.into_iter().zip(graphics) {
+        unsafe { write_register(GRAPHICS_INDEX, register, value) };
+    }
+}
+
+/// Reads a register of the sequencer or graphics controller, whose data port follows the index
+unsafe fn read_register(index_port: u16, register: u8) -> u8 {
+    unsafe {
+        Port::new(index_port).write(register);
+        Port::new(index_port + 1).read()
+    }
+}
+
+unsafe fn write_register(index_port: u16, register: u8, value: u8) {
+    unsafe {
+        Port::new(index_port).write(register);
+        Port::new(index_port + 1).write(value);
+    }
+}
+
+/// A PSF2 font with two 4x2 glyphs, `a` and `b`, and a sequence for the first one
+#[cfg(test)]
+#[rustfmt::skip]
+const PSF2_FONT: [u8; 32 + 4 + 9] = [
+    0x72, 0xb5, 0x4a, 0x86, // magic
+    0, 0, 0, 0, // version
+    32, 0, 0, 0, // header size
+    1, 0, 0, 0, // flags, has a table
+    2, 0, 0, 0, // glyphs
+    2, 0, 0, 0, // bytes per glyph
+    2, 0, 0, 0, // height
+    4, 0, 0, 0, // width
+    0b1000_0000, 0b0001_0000, // a
+    0b1111_0000, 0b0000_0000, // b
+    b'a', 0xfe, b'x', b'y', 0xff, // a, and the sequence `xy`
+    b'b', 0xc3, 0xa9, 0xff, // b and é
+];
+
+#[test_case]
+fn test_parse_psf2() {
+    let font = Font::parse(&PSF2_FONT).expect("parsing failed");
+    assert_eq!((font.width(), font.height()), (4, 2));
+    assert_eq!(font.glyph_index('a'), Some(0));
+    assert_eq!(font.glyph_index('é'), Some(1));
+    // Only part of a sequence
+    assert_eq!(font.glyph_index('x'), None);
+
+    let glyph = font.glyph('a').expect("no glyph");
+    assert!(glyph.is_set(0, 0));
+    assert!(!glyph.is_set(1, 0));
+    assert!(glyph.is_set(3, 1));
+    assert!(!glyph.is_set(4, 1));
+}
+
+#[test_case]
+fn test_parse_psf1_without_table() {
+    let mut font = [0; 4 + 256];
+    font[..4].copy_from_slice(&[0x36, 0x04, 0, 1]);
+    font[4 + 0x82] = 0xff;
+
+    let font = Font::parse(&font).expect("parsing failed");
+    let glyph = font.glyph('é').expect("no glyph");
+    assert!(glyph.is_set(7, 0));
+    assert!(font.glyph('€').is_none());
+}
+
+#[test_case]
+fn test_parse_errors() {
+    assert_eq!(Font::parse(b"not a font").err(), Some(FontError::BadMagic));
+    assert_eq!(
+        Font::parse(&PSF2_FONT[..40]).err(),
+        Some(FontError::Truncated)
+    );
+}
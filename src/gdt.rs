@@ -18,7 +18,7 @@ lazy_static! {
             // FIX: Replace by proper stack allocation
             static mut STACK: [u8; STACK_SIZE] = [0; STACK_SIZE];
 
-            let stack_start = VirtAddr::from_ptr(unsafe { &STACK });
+            let stack_start = VirtAddr::from_ptr(core::ptr::addr_of!(STACK));
             // Returning the stack end
             stack_start + STACK_SIZE
         };
@@ -0,0 +1,351 @@
+use crate::framebuffer::{self, Framebuffer, Rgb};
+use alloc::{vec, vec::Vec};
+
+/// Bytes of a pixel of an `Image`, red, green and blue
+const RGB_BYTES: usize = 3;
+
+/// Something pixels can be drawn on
+///
+/// Positions are signed, shapes may stick out of the canvas and only the part on it
+/// is drawn.
+pub trait Canvas {
+    /// Width and height in pixels
+    fn size(&self) -> (usize, usize);
+
+    /// Sets the pixel at `x`, `y`, which is always on the canvas
+    fn put_pixel(&mut self, x: usize, y: usize, color: Rgb);
+
+    /// Sets the pixel at `x`, `y` if it's on the canvas
+    fn draw_pixel(&mut self, x: isize, y: isize, color: Rgb) {
+        let (width, height) = self.size();
+        if let (Ok(x), Ok(y)) = (usize::try_from(x), usize::try_from(y)) {
+            if x < width && y < height {
+                self.put_pixel(x, y, color);
+            }
+        }
+    }
+
+    /// Draws a line between the points `from` and `to`, both included
+    fn draw_line(&mut self, from: (isize, isize), to: (isize, isize), color: Rgb) {
+        let ((x0, y0), (x1, y1)) = (from, to);
+        // Bresenham's algorithm, for every octant
+        let (dx, dy) = ((x1 - x0).abs(), -(y1 - y0).abs());
+        let (step_x, step_y) = ((x1 - x0).signum(), (y1 - y0).signum());
+        let (mut x, mut y) = (x0, y0);
+        let mut error = dx + dy;
+        loop {
+            self.draw_pixel(x, y, color);
+            if (x, y) == (x1, y1) {
+                break;
+            }
+            let doubled = 2 * error;
+            if doubled >= dy {
+                error += dy;
+                x += step_x;
+            }
+            if doubled <= dx {
+                error += dx;
+                y += step_y;
+            }
+        }
+    }
+
+    /// Fills the rectangle with the top left corner at `x`, `y` with `color`
+    fn fill_rect(&mut self, x: isize, y: isize, width: usize, height: usize, color: Rgb) {
+        let (canvas_width, canvas_height) = self.size();
+        let clip = |start: isize, length: usize, limit: usize| {
+            let end = start.saturating_add_unsigned(length);
+            let clamp = |value: isize| usize::try_from(value).unwrap_or(0).min(limit);
+            clamp(start)..clamp(end)
+        };
+        for y in clip(y, height, canvas_height) {
+            for x in clip(x, width, canvas_width) {
+                self.put_pixel(x, y, color);
+            }
+        }
+    }
+
+    /// Draws the one pixel wide outline of a rectangle
+    fn draw_rect(&mut self, x: isize, y: isize, width: usize, height: usize, color: Rgb) {
+        if width == 0 || height == 0 {
+            return;
+        }
+        let right = x.saturating_add_unsigned(width - 1);
+        let bottom = y.saturating_add_unsigned(height - 1);
+        self.fill_rect(x, y, width, 1, color);
+        self.fill_rect(x, bottom, width, 1, color);
+        self.fill_rect(x, y, 1, height, color);
+        self.fill_rect(right, y, 1, height, color);
+    }
+
+    /// Draws the outline of a circle
+    fn draw_circle(&mut self, (x, y): (isize, isize), radius: usize, color: Rgb) {
+        for (dx, dy) in circle_octant(radius) {
+            for (dx, dy) in [(dx, dy), (dy, dx)] {
+                self.draw_pixel(x + dx, y + dy, color);
+                self.draw_pixel(x - dx, y + dy, color);
+                self.draw_pixel(x + dx, y - dy, color);
+                self.draw_pixel(x - dx, y - dy, color);
+            }
+        }
+    }
+
+    /// Draws a circle filled with `color`
+    fn fill_circle(&mut self, (x, y): (isize, isize), radius: usize, color: Rgb) {
+        for (dx, dy) in circle_octant(radius) {
+            // Spans between the points mirrored on the vertical axis
+            for (dx, dy) in [(dx, dy), (dy, dx)] {
+                let width = dx.unsigned_abs() * 2 + 1;
+                self.fill_rect(x - dx, y + dy, width, 1, color);
+                self.fill_rect(x - dx, y - dy, width, 1, color);
+            }
+        }
+    }
+
+    /// Copies `image` to the canvas with its top left corner at `x`, `y`
+    fn blit(&mut self, x: isize, y: isize, image: &Image) {
+        for (row, y) in (0..image.height).zip(y..) {
+            for (col, x) in (0..image.width).zip(x..) {
+                self.draw_pixel(x, y, image.pixel(col, row));
+            }
+        }
+    }
+
+    /// Fills the whole canvas with `color`
+    fn clear(&mut self, color: Rgb) {
+        let (width, height) = self.size();
+        self.fill_rect(0, 0, width, height, color);
+    }
+}
+
+/// Returns the points of the circle from straight down to 45 degrees, as offsets
+/// from the center
+///
+/// The midpoint circle algorithm, the rest of the circle is mirrored.
+fn circle_octant(radius: usize) -> impl Iterator<Item = (isize, isize)> {
+    let mut x = isize::try_from(radius).unwrap_or(isize::MAX);
+    let mut y = 0;
+    let mut error = 1 - x;
+    core::iter::from_fn(move || {
+        if y > x {
+            return None;
+        }
+        let point = (y, x);
+        y += 1;
+        if error < 0 {
+            error += 2 * y + 1;
+        } else {
+            x -= 1;
+            error += 2 * (y - x) + 1;
+        }
+        Some(point)
+    })
+}
+
+/// An image of 24 bit RGB pixels, row by row, like the pixel data of a binary PPM file
+#[derive(Debug, Clone, Copy)]
+pub struct Image<'a> {
+    width: usize,
+    height: usize,
+    data: &'a [u8],
+}
+
+impl<'a> Image<'a> {
+    /// Returns `None` if `data` isn't exactly `width` * `height` pixels
+    #[must_use]
+    pub fn new(width: usize, height: usize, data: &'a [u8]) -> Option<Self> {
+        let size = width.checked_mul(height)?.checked_mul(RGB_BYTES)?;
+        (data.len() == size).then_some(Self {
+            width,
+            height,
+            data,
+        })
+    }
+
+    #[must_use]
+    pub const fn width(&self) -> usize {
+        self.width
+    }
+
+    #[must_use]
+    pub const fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Returns the color of the pixel at `x`, `y`
+    ///
+    /// # Panics
+    ///
+    /// Panics if the pixel is outside of the image.
+    #[must_use]
+    pub fn pixel(&self, x: usize, y: usize) -> Rgb {
+        assert!(
+            x < self.width && y < self.height,
+            "pixel outside of the image"
+        );
+        let offset = (y * self.width + x) * RGB_BYTES;
+        Rgb::new(
+            self.data[offset],
+            self.data[offset + 1],
+            self.data[offset + 2],
+        )
+    }
+}
+
+/// A canvas in memory, to draw something once and read it back or draw it later
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Bitmap {
+    width: usize,
+    height: usize,
+    pixels: Vec<Rgb>,
+}
+
+impl Bitmap {
+    /// Creates a black bitmap
+    #[must_use]
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![Rgb::BLACK; width * height],
+        }
+    }
+
+    /// Returns the color of the pixel at `x`, `y`, `None` outside of the bitmap
+    #[must_use]
+    pub fn pixel(&self, x: usize, y: usize) -> Option<Rgb> {
+        (x < self.width && y < self.height).then(|| self.pixels[y * self.width + x])
+    }
+}
+
+impl Canvas for Bitmap {
+    fn size(&self) -> (usize, usize) {
+        (self.width, self.height)
+    }
+
+    fn put_pixel(&mut self, x: usize, y: usize, color: Rgb) {
+        self.pixels[y * self.width + x] = color;
+    }
+}
+
+/// Two pages of the framebuffer, one shown while the other is drawn
+///
+/// The console draws into the first page, while the second one is shown its output
+/// stays hidden, and drawing into the first page covers it. Dropping it shows the
+/// first page again and draws the console over whatever was left there.
+#[derive(Debug)]
+pub struct DoubleBuffer {
+    pages: [Framebuffer; 2],
+    /// Index of the page on the screen
+    shown: usize,
+}
+
+impl DoubleBuffer {
+    /// Returns `None` without a framebuffer or if it only has memory for one page
+    #[must_use]
+    pub fn new() -> Option<Self> {
+        Some(Self {
+            pages: [framebuffer::page(0)?, framebuffer::page(1)?],
+            shown: 0,
+        })
+    }
+
+    /// Uses `pages` instead of the ones of the framebuffer
+    #[cfg(test)]
+    pub(crate) const fn from_pages(pages: [Framebuffer; 2]) -> Self {
+        Self { pages, shown: 0 }
+    }
+
+    /// Shows the page that was drawn, the other one can be drawn next
+    pub fn flip(&mut self) {
+        self.shown = 1 - self.shown;
+        framebuffer::show_page(self.shown);
+    }
+
+    /// The page that isn't shown
+    fn back(&mut self) -> &mut Framebuffer {
+        &mut self.pages[1 - self.shown]
+    }
+}
+
+impl Canvas for DoubleBuffer {
+    fn size(&self) -> (usize, usize) {
+        self.pages[0].size()
+    }
+
+    fn put_pixel(&mut self, x: usize, y: usize, color: Rgb) {
+        self.back().put_pixel(x, y, color);
+    }
+}
+
+impl Drop for DoubleBuffer {
+    fn drop(&mut self) {
+        framebuffer::show_page(0);
+        crate::vga_buffer::console::redraw_screen();
+    }
+}
+
+#[cfg(test)]
+const RED: Rgb = Rgb::new(0xff, 0, 0);
+
+#[test_case]
+fn test_lines_in_every_direction() {
+    let mut bitmap = Bitmap::new(8, 8);
+    bitmap.draw_line((7, 7), (0, 0), RED);
+    bitmap.draw_line((0, 7), (6, 4), RED);
+    assert!((0..8).all(|i| bitmap.pixel(i, i) == Some(RED)));
+    assert_eq!(bitmap.pixel(0, 7), Some(RED));
+    assert_eq!(bitmap.pixel(6, 4), Some(RED));
+    assert_eq!(bitmap.pixel(7, 0), Some(Rgb::BLACK));
+}
+
+#[test_case]
+fn test_shapes_are_clipped() {
+    let mut bitmap = Bitmap::new(4, 4);
+    bitmap.fill_rect(-2, 2, 100, 100, RED);
+    assert_eq!(bitmap.pixel(0, 2), Some(RED));
+    assert_eq!(bitmap.pixel(3, 3), Some(RED));
+    assert_eq!(bitmap.pixel(0, 1), Some(Rgb::BLACK));
+
+    bitmap.draw_line((-10, 0), (10, 0), Rgb::WHITE);
+    assert!((0..4).all(|x| bitmap.pixel(x, 0) == Some(Rgb::WHITE)));
+}
+
+#[test_case]
+fn test_rect_outline() {
+    let mut bitmap = Bitmap::new(5, 5);
+    bitmap.draw_rect(1, 1, 3, 3, RED);
+    assert_eq!(bitmap.pixel(1, 1), Some(RED));
+    assert_eq!(bitmap.pixel(3, 3), Some(RED));
+    assert_eq!(bitmap.pixel(2, 2), Some(Rgb::BLACK));
+    assert_eq!(bitmap.pixel(4, 4), Some(Rgb::BLACK));
+}
+
+#[test_case]
+fn test_circles() {
+    let mut bitmap = Bitmap::new(11, 11);
+    bitmap.draw_circle((5, 5), 5, RED);
+    for (x, y) in [(5, 0), (0, 5), (10, 5), (5, 10)] {
+        assert_eq!(bitmap.pixel(x, y), Some(RED));
+    }
+    assert_eq!(bitmap.pixel(5, 5), Some(Rgb::BLACK));
+    assert_eq!(bitmap.pixel(0, 0), Some(Rgb::BLACK));
+
+    bitmap.fill_circle((5, 5), 5, Rgb::WHITE);
+    assert_eq!(bitmap.pixel(5, 5), Some(Rgb::WHITE));
+    assert_eq!(bitmap.pixel(2, 5), Some(Rgb::WHITE));
+    assert_eq!(bitmap.pixel(0, 0), Some(Rgb::BLACK));
+}
+
+#[test_case]
+fn test_blit_image() {
+    let data = [0xff, 0, 0, 0, 0xff, 0, 0, 0, 0xff, 0xff, 0xff, 0xff];
+    let image = Image::new(2, 2, &data).expect("wrong size");
+    assert!(Image::new(3, 2, &data).is_none());
+
+    let mut bitmap = Bitmap::new(3, 3);
+    bitmap.blit(1, -1, &image);
+    assert_eq!(bitmap.pixel(1, 0), Some(Rgb::new(0, 0, 0xff)));
+    assert_eq!(bitmap.pixel(2, 0), Some(Rgb::WHITE));
+    assert_eq!(bitmap.pixel(1, 1), Some(Rgb::BLACK));
+}
@@ -1,130 +1,466 @@
-use crate::gdt;
-use crate::hlt_loop;
-use crate::println;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use pic8259::ChainedPics;
 use spin::Mutex;
-use x86_64::structures::idt::PageFaultErrorCode;
-use x86_64::structures::idt::{InterruptDescriptorTable, InterruptStackFrame};
+use x86_64::structures::idt::{HandlerFunc, InterruptDescriptorTable, InterruptStackFrame};
+use x86_64::{
+    structures::paging::{mapper::MapToError, FrameAllocator, Mapper, Size4KiB},
+    PhysAddr,
+};
+
+/// Local APIC and IOAPIC backend replacing the PICs
+pub mod apic;
+/// Handlers for the CPU exceptions
+mod exceptions;
 
 /// Offset of the first PIC
 pub const PIC_1_OFFSET: u8 = 32;
-/// Offset of the last PIC
-pub const PIC_2_OFFSET: u8 = PIC_1_OFFSET + 1;
+/// Offset of the last PIC, right after the eight vectors of the first one
+pub const PIC_2_OFFSET: u8 = PIC_1_OFFSET + 8;
 
 /// Represents the primary/secondary PIC layout
 pub static PICS: Mutex<ChainedPics> =
     Mutex::new(unsafe { ChainedPics::new(PIC_1_OFFSET, PIC_2_OFFSET) });
 
+/// Number of IRQ lines of the chained PICs
+pub const IRQ_LINES: u8 = 16;
+/// Maximum number of handlers sharing a single IRQ line
+pub const MAX_HANDLERS_PER_LINE: usize = 4;
+
+/// IRQ line of the programmable interval timer
+pub const TIMER_IRQ: u8 = 0;
+/// IRQ line of the PS/2 keyboard
+pub const KEYBOARD_IRQ: u8 = 1;
+/// IRQ line of the first serial port
+pub const SERIAL_IRQ: u8 = 4;
+/// IRQ line the secondary PIC is chained to on the primary one
+const CASCADE_IRQ: u8 = 2;
+
+/// Command ports of the primary and secondary PIC
+const PIC_COMMAND_PORTS: [u16; 2] = [0x20, 0xA0];
+/// OCW3 command to read the in-service register on the next read of the command port
+const PIC_READ_ISR: u8 = 0x0B;
+
+/// Number of spurious IRQs received since boot
+static SPURIOUS_IRQS: AtomicUsize = AtomicUsize::new(0);
+
+/// Whether the IRQs are delivered by the APIC instead of the PICs
+static APIC_ACTIVE: AtomicBool = AtomicBool::new(false);
+
+/// The hardware delivering the IRQs to the CPU
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InterruptController {
+    /// The legacy chained 8259 PICs
+    Pic,
+    /// The local APIC together with the IOAPIC
+    Apic,
+}
+
+/// A function called when its IRQ line fires
+///
+/// It runs with interrupts disabled and the end of interrupt is sent after it returns.
+pub type IrqHandler = fn();
+
+/// Errors returned by the IRQ registration functions
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IrqError {
+    /// The line isn't one of the `IRQ_LINES`
+    InvalidLine(u8),
+    /// The line already has `MAX_HANDLERS_PER_LINE` handlers
+    LineFull(u8),
+    /// The handler isn't registered on the line
+    NotRegistered(u8),
+}
+
+/// Registered handlers of every IRQ line
+static IRQ_HANDLERS: Mutex<[[Option<IrqHandler>; MAX_HANDLERS_PER_LINE]; IRQ_LINES as usize]> =
+    Mutex::new([[None; MAX_HANDLERS_PER_LINE]; IRQ_LINES as usize]);
+
 lazy_static::lazy_static! {
     static ref IDT: InterruptDescriptorTable = {
         let mut idt = InterruptDescriptorTable::new();
-        idt.breakpoint.set_handler_fn(breakpoint_handler);
-        unsafe {
-            idt.double_fault.set_handler_fn(double_fault_handler).set_stack_index(gdt::DOUBLE_FAULT_IST_INDEX);
+        exceptions::install(&mut idt);
+        for line in 0..IRQ_LINES {
+            idt[usize::from(irq_vector(line))].set_handler_fn(IRQ_STUBS[usize::from(line)]);
         }
-        idt.page_fault.set_handler_fn(page_fault_handler);
-        idt[InterruptIndex::Timer.as_usize()].set_handler_fn(timer_interrupt_handler);
-        idt[InterruptIndex::Keyboard.as_usize()].set_handler_fn(keyboard_interrupt_handler);
+        idt[usize::from(apic::SPURIOUS_VECTOR)].set_handler_fn(apic_spurious_handler);
 
         idt
     };
 }
 
-/// Represents the index of interrupt
-#[derive(Debug, Clone, Copy)]
-#[repr(u8)]
-pub enum InterruptIndex {
-    /// A timer interrupt is the first one
-    Timer = PIC_1_OFFSET,
-    /// A keyboard interrupt
-    Keyboard,
+/// Loads the `InterruptDescriptorTable`
+pub fn init_idt() {
+    IDT.load();
+}
+
+/// Initializes the PICs with every line masked and registers the built-in IRQ handlers
+pub fn init_pics() {
+    unsafe {
+        let mut pics = PICS.lock();
+        pics.initialize();
+        pics.disable();
+    }
+
+    register_irq(TIMER_IRQ, timer_handler).expect("Registering the timer handler failed");
+    register_irq(KEYBOARD_IRQ, keyboard_handler).expect("Registering the keyboard handler failed");
+    register_irq(SERIAL_IRQ, serial_handler).expect("Registering the serial handler failed");
 }
 
-impl InterruptIndex {
-    const fn as_u8(self) -> u8 {
-        self as u8
+/// Switches the IRQ delivery to the `preferred` controller
+///
+/// The IOAPIC and the IRQ overrides are taken from the ACPI tables when `acpi::init` was called.
+/// The APIC falls back to the PICs when the CPU doesn't have one. Every registered
+/// handler keeps working, only the masking and end of interrupt change.
+/// Returns the controller that is in use afterwards.
+///
+/// # Errors
+///
+/// Returns an error if the APIC registers could not be mapped, the PICs stay in use then.
+pub fn select_controller(
+    preferred: InterruptController,
+    mapper: &mut impl Mapper<Size4KiB>,
+    frame_allocator: &mut impl FrameAllocator<Size4KiB>,
+) -> Result<InterruptController, MapToError<Size4KiB>> {
+    if preferred == InterruptController::Pic || !apic::is_supported() {
+        return Ok(active_controller());
     }
 
-    fn as_usize(self) -> usize {
-        usize::from(self.as_u8())
+    // Without the ACPI tables we have to trust the usual PC layout
+    let io_apic_address =
+        crate::acpi::isa_io_apic_address().unwrap_or(PhysAddr::new(apic::DEFAULT_IO_APIC_ADDRESS));
+    let overrides = crate::acpi::tables()
+        .and_then(|tables| tables.madt.as_ref())
+        .map(|madt| madt.interrupt_overrides.as_slice())
+        .unwrap_or_default();
+    for irq_override in overrides.iter().filter(|o| o.source < IRQ_LINES) {
+        apic::set_irq_route(
+            irq_override.source,
+            apic::IrqRoute {
+                gsi: irq_override.gsi,
+                active_low: irq_override.active_low,
+                level_triggered: irq_override.level_triggered,
+            },
+        );
     }
+
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        apic::init(io_apic_address, mapper, frame_allocator)?;
+
+        unsafe { PICS.lock().disable() };
+        APIC_ACTIVE.store(true, Ordering::SeqCst);
+
+        // Lines with handlers have to be unmasked on the IOAPIC as well
+        let handlers = *IRQ_HANDLERS.lock();
+        for (line, line_handlers) in (0..IRQ_LINES).zip(handlers) {
+            if line_handlers.iter().any(Option::is_some) {
+                apic::set_irq_masked(line, false);
+            }
+        }
+
+        Ok(InterruptController::Apic)
+    })
 }
 
-/// Loads the `InterruptDescriptorTable`
-pub fn init_idt() {
-    IDT.load();
+/// Returns the controller currently delivering the IRQs
+#[must_use]
+pub fn active_controller() -> InterruptController {
+    if APIC_ACTIVE.load(Ordering::SeqCst) {
+        InterruptController::Apic
+    } else {
+        InterruptController::Pic
+    }
 }
 
-/// Handler for breakpoint exception
-extern "x86-interrupt" fn breakpoint_handler(stack_frame: InterruptStackFrame) {
-    println!("EXCEPTION: BREAPOINT\n{:#?}", stack_frame);
+/// Returns the interrupt vector the PICs use for the IRQ `line`
+#[must_use]
+pub const fn irq_vector(line: u8) -> u8 {
+    if line < 8 {
+        PIC_1_OFFSET + line
+    } else {
+        PIC_2_OFFSET + line - 8
+    }
 }
 
-/// Handler for double fault exception
-extern "x86-interrupt" fn double_fault_handler(
-    stack_frame: InterruptStackFrame,
-    _error_code: u64,
-) -> ! {
-    panic!("EXCEPTION: DOUBLE FAULT\n{:#?}", stack_frame);
+/// Adds the `handler` to the IRQ `line` and unmasks the line
+///
+/// # Errors
+///
+/// Fails if the line doesn't exist or is already shared by too many handlers.
+pub fn register_irq(line: u8, handler: IrqHandler) -> Result<(), IrqError> {
+    if line >= IRQ_LINES {
+        return Err(IrqError::InvalidLine(line));
+    }
+
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let mut handlers = IRQ_HANDLERS.lock();
+        let slot = handlers[usize::from(line)]
+            .iter_mut()
+            .find(|slot| slot.is_none())
+            .ok_or(IrqError::LineFull(line))?;
+        *slot = Some(handler);
+        Ok(())
+    })?;
+
+    unmask_irq(line);
+    Ok(())
 }
 
-/// Handler for page fault exception
-extern "x86-interrupt" fn page_fault_handler(
-    stack_frame: InterruptStackFrame,
-    error_code: PageFaultErrorCode,
-) {
-    use x86_64::registers::control::Cr2;
+/// Removes the `handler` from the IRQ `line`, the line is masked once it has no handlers left
+///
+/// # Errors
+///
+/// Fails if the line doesn't exist or the handler isn't registered on it.
+pub fn unregister_irq(line: u8, handler: IrqHandler) -> Result<(), IrqError> {
+    if line >= IRQ_LINES {
+        return Err(IrqError::InvalidLine(line));
+    }
+
+    let line_empty = x86_64::instructions::interrupts::without_interrupts(|| {
+        let mut handlers = IRQ_HANDLERS.lock();
+        let line_handlers = &mut handlers[usize::from(line)];
+        let slot = line_handlers
+            .iter_mut()
+            .find(|slot| slot.is_some_and(|registered| core::ptr::fn_addr_eq(registered, handler)))
+            .ok_or(IrqError::NotRegistered(line))?;
+        *slot = None;
+        Ok(line_handlers.iter().all(Option::is_none))
+    })?;
 
-    println!("EXCEPTION: PAGE FAULT");
-    println!("Accessed address: {:?}", Cr2::read());
-    println!("Error code: {:?}", error_code);
-    println!("{stack_frame:#?}");
-    hlt_loop();
+    if line_empty {
+        mask_irq(line);
+    }
+    Ok(())
 }
 
-/// Handler for timer interrupt
-extern "x86-interrupt" fn timer_interrupt_handler(_stack_frame: InterruptStackFrame) {
-    crate::print!(".");
+/// Stops the PICs from raising the IRQ `line`
+pub fn mask_irq(line: u8) {
+    set_irq_masked(line, true);
+}
 
-    unsafe {
-        PICS.lock()
-            .notify_end_of_interrupt(InterruptIndex::Timer.as_u8());
-    };
+/// Lets the PICs raise the IRQ `line` again
+pub fn unmask_irq(line: u8) {
+    set_irq_masked(line, false);
+    // Lines of the secondary PIC only arrive through the cascade line
+    if line >= 8 && active_controller() == InterruptController::Pic {
+        set_irq_masked(CASCADE_IRQ, false);
+    }
 }
 
-/// Handler for keyboard interrupt
-extern "x86-interrupt" fn keyboard_interrupt_handler(_stack_frame: InterruptStackFrame) {
-    use crate::print;
-    use lazy_static::lazy_static;
-    use pc_keyboard::{layouts, DecodedKey, HandleControl, Keyboard, ScancodeSet1};
+fn set_irq_masked(line: u8, masked: bool) {
+    assert!(line < IRQ_LINES, "IRQ line {line} doesn't exist");
+
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        if active_controller() == InterruptController::Apic {
+            apic::set_irq_masked(line, masked);
+            return;
+        }
+
+        let mut pics = PICS.lock();
+        let mut masks = unsafe { pics.read_masks() };
+        let mask = &mut masks[usize::from(line / 8)];
+        if masked {
+            *mask |= 1 << (line % 8);
+        } else {
+            *mask &= !(1 << (line % 8));
+        }
+        unsafe { pics.write_masks(masks[0], masks[1]) };
+    });
+}
+
+/// Number of spurious IRQs ignored since boot
+#[must_use]
+pub fn spurious_irqs() -> usize {
+    SPURIOUS_IRQS.load(Ordering::Relaxed)
+}
+
+/// Checks whether the PIC really has the lowest priority `line` (7 or 15) in service
+///
+/// When an IRQ goes away before the CPU acknowledges it, the PIC raises its
+/// lowest priority line instead, without setting the in-service bit.
+fn is_spurious(line: u8) -> bool {
     use x86_64::instructions::port::Port;
 
-    lazy_static! {
-        static ref KEYBOARD: Mutex<Keyboard<layouts::Us104Key, ScancodeSet1>> = Mutex::new(
-            Keyboard::new(layouts::Us104Key, ScancodeSet1, HandleControl::Ignore,)
-        );
-    };
+    if line % 8 != 7 {
+        return false;
+    }
 
-    let mut keyboard = KEYBOARD.lock();
-    let mut port = Port::new(0x60);
+    let mut command: Port<u8> = Port::new(PIC_COMMAND_PORTS[usize::from(line / 8)]);
+    let in_service = unsafe {
+        command.write(PIC_READ_ISR);
+        command.read()
+    };
+    in_service & (1 << 7) == 0
+}
 
-    let scancode: u8 = unsafe { port.read() };
-    if let Ok(Some(key_event)) = keyboard.add_byte(scancode) {
-        if let Some(key) = keyboard.process_keyevent(key_event) {
-            match key {
-                DecodedKey::Unicode(character) => print!("{character}"),
-                DecodedKey::RawKey(key) => print!("{key:?}"),
-            }
+/// Calls every handler registered on the IRQ `line` and notifies the PICs
+fn dispatch_irq(line: u8) {
+    let apic_active = active_controller() == InterruptController::Apic;
+    if !apic_active && is_spurious(line) {
+        SPURIOUS_IRQS.fetch_add(1, Ordering::Relaxed);
+        // The primary PIC did send us the cascade line, so it still wants its EOI
+        if line >= 8 {
+            unsafe {
+                PICS.lock().notify_end_of_interrupt(irq_vector(CASCADE_IRQ));
+            };
         }
+        return;
     }
 
-    unsafe {
-        PICS.lock()
-            .notify_end_of_interrupt(InterruptIndex::Keyboard.as_u8());
+    // Copy the handlers out, so they can (un)register handlers themselves
+    let handlers = IRQ_HANDLERS.lock()[usize::from(line)];
+    for handler in handlers.into_iter().flatten() {
+        handler();
+    }
+
+    if apic_active {
+        apic::end_of_interrupt();
+    } else {
+        unsafe {
+            PICS.lock().notify_end_of_interrupt(irq_vector(line));
+        };
+    }
+
+    // Only now another thread may run, it would never see a timer tick without the EOI
+    crate::thread::preempt_if_requested();
+}
+
+/// Handler for the spurious interrupts of the local APIC, they don't get an EOI
+extern "x86-interrupt" fn apic_spurious_handler(_stack_frame: InterruptStackFrame) {
+    SPURIOUS_IRQS.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Generates an IDT entry point for every IRQ line, which forwards to `dispatch_irq`
+macro_rules! irq_stubs {
+    ($($line:literal => $stub:ident),* $(,)?) => {
+        $(
+            extern "x86-interrupt" fn $stub(_stack_frame: InterruptStackFrame) {
+                dispatch_irq($line);
+            }
+        )*
+
+        /// IDT entry points indexed by the IRQ line
+        const IRQ_STUBS: [HandlerFunc; IRQ_LINES as usize] = [$($stub),*];
     };
 }
 
+irq_stubs! {
+    0 => irq0_stub,
+    1 => irq1_stub,
+    2 => irq2_stub,
+    3 => irq3_stub,
+    4 => irq4_stub,
+    5 => irq5_stub,
+    6 => irq6_stub,
+    7 => irq7_stub,
+    8 => irq8_stub,
+    9 => irq9_stub,
+    10 => irq10_stub,
+    11 => irq11_stub,
+    12 => irq12_stub,
+    13 => irq13_stub,
+    14 => irq14_stub,
+    15 => irq15_stub,
+}
+
+/// Handler for timer interrupt
+fn timer_handler() {
+    crate::time::tick();
+}
+
+/// Handler for keyboard interrupt
+///
+/// The scancode is only queued, decoding happens in the tasks reading the keyboard.
+fn keyboard_handler() {
+    use x86_64::instructions::port::Port;
+
+    let mut port = Port::new(0x60);
+    let scancode: u8 = unsafe { port.read() };
+    crate::task::keyboard::add_scancode(scancode);
+}
+
+/// Handler for the interrupt of the first serial port
+///
+/// Like the scancodes, the received bytes are only queued.
+fn serial_handler() {
+    while let Some(byte) = crate::serial::try_receive() {
+        crate::task::serial::add_byte(byte);
+    }
+}
+
 #[test_case]
 fn test_breakpoint_interrupt() {
     x86_64::instructions::interrupts::int3();
 }
+
+#[test_case]
+fn test_debug_interrupt() {
+    unsafe { core::arch::asm!("int 1") };
+}
+
+#[test_case]
+fn test_non_maskable_interrupt() {
+    unsafe { core::arch::asm!("int 2") };
+}
+
+#[test_case]
+fn test_overflow_interrupt() {
+    unsafe { core::arch::asm!("int 4") };
+}
+
+#[cfg(test)]
+mod irq_tests {
+    use super::{register_irq, unregister_irq, IrqError, IRQ_LINES, PIC_1_OFFSET};
+    use core::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Parallel port 2, nothing is connected to it in QEMU
+    const TEST_LINE: u8 = 5;
+
+    static CALLS: AtomicUsize = AtomicUsize::new(0);
+
+    fn count_call() {
+        CALLS.fetch_add(1, Ordering::SeqCst);
+    }
+
+    fn count_another_call() {
+        CALLS.fetch_add(10, Ordering::SeqCst);
+    }
+
+    fn raise_test_line() {
+        unsafe { core::arch::asm!("int {vector}", vector = const PIC_1_OFFSET + TEST_LINE) };
+    }
+
+    #[test_case]
+    fn test_registered_handler_is_called() {
+        CALLS.store(0, Ordering::SeqCst);
+        register_irq(TEST_LINE, count_call).expect("Registering failed");
+        raise_test_line();
+        unregister_irq(TEST_LINE, count_call).expect("Unregistering failed");
+        raise_test_line();
+
+        assert_eq!(CALLS.load(Ordering::SeqCst), 1);
+    }
+
+    #[test_case]
+    fn test_shared_line() {
+        CALLS.store(0, Ordering::SeqCst);
+        register_irq(TEST_LINE, count_call).expect("Registering failed");
+        register_irq(TEST_LINE, count_another_call).expect("Registering failed");
+        raise_test_line();
+        unregister_irq(TEST_LINE, count_call).expect("Unregistering failed");
+        unregister_irq(TEST_LINE, count_another_call).expect("Unregistering failed");
+
+        assert_eq!(CALLS.load(Ordering::SeqCst), 11);
+    }
+
+    #[test_case]
+    fn test_invalid_line() {
+        assert_eq!(
+            register_irq(IRQ_LINES, count_call),
+            Err(IrqError::InvalidLine(IRQ_LINES))
+        );
+        assert_eq!(
+            unregister_irq(TEST_LINE, count_call),
+            Err(IrqError::NotRegistered(TEST_LINE))
+        );
+    }
+}
@@ -0,0 +1,255 @@
+use super::{IRQ_LINES, PIC_1_OFFSET};
+use crate::memory;
+use core::sync::atomic::{AtomicU64, Ordering};
+use spin::Mutex;
+use x86_64::{
+    registers::model_specific::Msr,
+    structures::paging::{mapper::MapToError, FrameAllocator, Mapper, Size4KiB},
+    PhysAddr, VirtAddr,
+};
+
+/// Model specific register holding the local APIC base address
+const IA32_APIC_BASE_MSR: u32 = 0x1B;
+/// Enables the local APIC in `IA32_APIC_BASE_MSR`
+const APIC_BASE_ENABLE: u64 = 1 << 11;
+/// Physical address of the first IOAPIC on every PC we care about
+pub const DEFAULT_IO_APIC_ADDRESS: u64 = 0xFEC0_0000;
+
+/// Vector the local APIC uses for spurious interrupts, it never needs an EOI
+pub const SPURIOUS_VECTOR: u8 = 0xFF;
+
+/// Local APIC register offsets
+const LAPIC_ID: usize = 0x20;
+const LAPIC_TASK_PRIORITY: usize = 0x80;
+const LAPIC_EOI: usize = 0xB0;
+const LAPIC_SPURIOUS: usize = 0xF0;
+const LAPIC_LVT_LINT0: usize = 0x350;
+const LAPIC_LVT_LINT1: usize = 0x360;
+/// Software enable bit of the spurious interrupt register
+const LAPIC_SOFTWARE_ENABLE: u32 = 1 << 8;
+/// Mask bit of the local vector table entries
+const LVT_MASKED: u32 = 1 << 16;
+
+/// IOAPIC register offsets
+const IOAPIC_VERSION: u32 = 0x01;
+const IOAPIC_REDIRECTION_TABLE: u32 = 0x10;
+/// Redirection entry flags
+const REDIRECTION_ACTIVE_LOW: u64 = 1 << 13;
+const REDIRECTION_LEVEL_TRIGGERED: u64 = 1 << 15;
+const REDIRECTION_MASKED: u64 = 1 << 16;
+
+/// Virtual address of the mapped local APIC registers, zero until `init` is done
+static LOCAL_APIC: AtomicU64 = AtomicU64::new(0);
+/// The IOAPIC the legacy IRQs are routed through
+static IO_APIC: Mutex<Option<IoApic>> = Mutex::new(None);
+
+/// Where a legacy IRQ line is connected on the IOAPIC
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IrqRoute {
+    /// Global system interrupt, which is the IOAPIC input pin
+    pub gsi: u32,
+    /// The line signals with low voltage instead of high
+    pub active_low: bool,
+    /// The line is level triggered instead of edge triggered
+    pub level_triggered: bool,
+}
+
+impl IrqRoute {
+    /// An edge triggered, active high ISA line connected to the `gsi`
+    #[must_use]
+    pub const fn isa(gsi: u32) -> Self {
+        Self {
+            gsi,
+            active_low: false,
+            level_triggered: false,
+        }
+    }
+}
+
+/// Routes of the legacy IRQ lines, indexed by the line
+///
+/// The timer is wired to pin 2 on almost every PC, every other line is identity mapped.
+static IRQ_ROUTES: Mutex<[IrqRoute; IRQ_LINES as usize]> = Mutex::new({
+    let mut routes = [IrqRoute::isa(0); IRQ_LINES as usize];
+    let mut line = 0;
+    while line < IRQ_LINES as usize {
+        routes[line] = IrqRoute::isa(line as u32);
+        line += 1;
+    }
+    routes[super::TIMER_IRQ as usize] = IrqRoute::isa(2);
+    routes
+});
+
+/// Checks CPUID for an on-chip local APIC
+#[must_use]
+pub fn is_supported() -> bool {
+    let features = core::arch::x86_64::__cpuid(1);
+    features.edx & (1 << 9) != 0
+}
+
+/// Overrides where the legacy IRQ `line` is connected, has to be called before `init`
+///
+/// # Panics
+///
+/// Panics if the line isn't one of the `IRQ_LINES`.
+pub fn set_irq_route(line: u8, route: IrqRoute) {
+    assert!(line < IRQ_LINES, "IRQ line {line} doesn't exist");
+    IRQ_ROUTES.lock()[usize::from(line)] = route;
+}
+
+/// Maps and enables the local APIC and the IOAPIC at `io_apic_address`
+///
+/// Every legacy IRQ line is routed to the same vector the PICs would use, but masked.
+///
+/// # Errors
+///
+/// Returns an error if the registers could not be mapped.
+pub(super) fn init(
+    io_apic_address: PhysAddr,
+    mapper: &mut impl Mapper<Size4KiB>,
+    frame_allocator: &mut impl FrameAllocator<Size4KiB>,
+) -> Result<(), MapToError<Size4KiB>> {
+    let mut apic_base_msr = Msr::new(IA32_APIC_BASE_MSR);
+    let apic_base = unsafe { apic_base_msr.read() };
+    // The address occupies the bits 12..52
+    let local_apic_address = PhysAddr::new(apic_base & 0x000F_FFFF_FFFF_F000);
+
+    let local_apic =
+        unsafe { memory::map_mmio(local_apic_address, 0x1000, mapper, frame_allocator)? };
+    let io_apic = unsafe { memory::map_mmio(io_apic_address, 0x20, mapper, frame_allocator)? };
+
+    unsafe { apic_base_msr.write(apic_base | APIC_BASE_ENABLE) };
+    LOCAL_APIC.store(local_apic.as_u64(), Ordering::Release);
+
+    unsafe {
+        // Accept every priority, mask the legacy pins and enable the APIC
+        write_local(LAPIC_TASK_PRIORITY, 0);
+        write_local(LAPIC_LVT_LINT0, LVT_MASKED);
+        write_local(LAPIC_LVT_LINT1, LVT_MASKED);
+        write_local(
+            LAPIC_SPURIOUS,
+            LAPIC_SOFTWARE_ENABLE | u32::from(SPURIOUS_VECTOR),
+        );
+    }
+
+    let io_apic = IoApic { base: io_apic };
+    let destination = unsafe { read_local(LAPIC_ID) } >> 24;
+    let pins = unsafe { io_apic.pins() };
+    let routes = *IRQ_ROUTES.lock();
+    for (line, route) in (0..IRQ_LINES).zip(routes) {
+        if route.gsi >= pins || !owns_pin(&routes, line) {
+            continue;
+        }
+
+        let mut entry = u64::from(PIC_1_OFFSET + line) | REDIRECTION_MASKED;
+        if route.active_low {
+            entry |= REDIRECTION_ACTIVE_LOW;
+        }
+        if route.level_triggered {
+            entry |= REDIRECTION_LEVEL_TRIGGERED;
+        }
+        entry |= u64::from(destination) << 56;
+        unsafe { io_apic.write_redirection(route.gsi, entry) };
+    }
+    *IO_APIC.lock() = Some(io_apic);
+
+    Ok(())
+}
+
+/// Whether the legacy IRQ `line` gets its pin on the IOAPIC
+///
+/// A line routed elsewhere by an override frees its own pin, which the identity
+/// mapped line of the same number would otherwise share. The timer takes the pin
+/// of the cascade line, which never fires under the APIC, like that.
+fn owns_pin(routes: &[IrqRoute; IRQ_LINES as usize], line: u8) -> bool {
+    let gsi = routes[usize::from(line)].gsi;
+    !(0..IRQ_LINES)
+        .zip(routes)
+        .any(|(other, route)| other != line && route.gsi == gsi && route.gsi != u32::from(other))
+}
+
+/// Returns the vector the IOAPIC pin `gsi` delivers, `None` before `init` or if
+/// there is no such pin
+#[must_use]
+pub fn redirection_vector(gsi: u32) -> Option<u8> {
+    let io_apic = IO_APIC.lock();
+    let io_apic = io_apic.as_ref()?;
+    unsafe { (gsi < io_apic.pins()).then(|| io_apic.read_redirection(gsi) as u8) }
+}
+
+/// Signals the end of the current interrupt to the local APIC
+pub(super) fn end_of_interrupt() {
+    unsafe { write_local(LAPIC_EOI, 0) };
+}
+
+/// (Un)masks the IOAPIC pin the legacy IRQ `line` is routed to
+pub(super) fn set_irq_masked(line: u8, masked: bool) {
+    let routes = *IRQ_ROUTES.lock();
+    // The pin belongs to another line, which must not be affected
+    if !owns_pin(&routes, line) {
+        return;
+    }
+    let gsi = routes[usize::from(line)].gsi;
+    if let Some(io_apic) = IO_APIC.lock().as_ref() {
+        unsafe {
+            let entry = io_apic.read_redirection(gsi);
+            let entry = if masked {
+                entry | REDIRECTION_MASKED
+            } else {
+                entry & !REDIRECTION_MASKED
+            };
+            io_apic.write_redirection(gsi, entry);
+        }
+    }
+}
+
+unsafe fn read_local(register: usize) -> u32 {
+    let base = LOCAL_APIC.load(Ordering::Acquire) as usize;
+    unsafe { core::ptr::read_volatile((base + register) as *const u32) }
+}
+
+unsafe fn write_local(register: usize, value: u32) {
+    let base = LOCAL_APIC.load(Ordering::Acquire) as usize;
+    unsafe { core::ptr::write_volatile((base + register) as *mut u32, value) };
+}
+
+/// The memory mapped registers of an IOAPIC
+struct IoApic {
+    base: VirtAddr,
+}
+
+impl IoApic {
+    unsafe fn read(&self, register: u32) -> u32 {
+        let select = self.base.as_mut_ptr::<u32>();
+        unsafe {
+            core::ptr::write_volatile(select, register);
+            core::ptr::read_volatile(select.byte_add(0x10))
+        }
+    }
+
+    unsafe fn write(&self, register: u32, value: u32) {
+        let select = self.base.as_mut_ptr::<u32>();
+        unsafe {
+            core::ptr::write_volatile(select, register);
+            core::ptr::write_volatile(select.byte_add(0x10), value);
+        }
+    }
+
+    /// Number of input pins of this IOAPIC
+    unsafe fn pins(&self) -> u32 {
+        ((unsafe { self.read(IOAPIC_VERSION) } >> 16) & 0xFF) + 1
+    }
+
+    unsafe fn read_redirection(&self, pin: u32) -> u64 {
+        let register = IOAPIC_REDIRECTION_TABLE + pin * 2;
+        unsafe { u64::from(self.read(register)) | (u64::from(self.read(register + 1)) << 32) }
+    }
+
+    unsafe fn write_redirection(&self, pin: u32, entry: u64) {
+        let register = IOAPIC_REDIRECTION_TABLE + pin * 2;
+        unsafe {
+            self.write(register, entry as u32);
+            self.write(register + 1, (entry >> 32) as u32);
+        }
+    }
+}
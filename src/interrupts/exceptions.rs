@@ -0,0 +1,163 @@
+use crate::gdt;
+use crate::{println, serial_println};
+use core::fmt;
+use x86_64::structures::idt::{
+    InterruptDescriptorTable, InterruptStackFrame, PageFaultErrorCode, SelectorErrorCode,
+};
+
+/// Sets handlers for every architectural exception in the `idt`
+pub(super) fn install(idt: &mut InterruptDescriptorTable) {
+    idt.divide_error.set_handler_fn(divide_error_handler);
+    idt.debug.set_handler_fn(debug_handler);
+    idt.non_maskable_interrupt
+        .set_handler_fn(non_maskable_interrupt_handler);
+    idt.breakpoint.set_handler_fn(breakpoint_handler);
+    idt.overflow.set_handler_fn(overflow_handler);
+    idt.bound_range_exceeded
+        .set_handler_fn(bound_range_exceeded_handler);
+    idt.invalid_opcode.set_handler_fn(invalid_opcode_handler);
+    idt.device_not_available
+        .set_handler_fn(device_not_available_handler);
+    unsafe {
+        idt.double_fault
+            .set_handler_fn(double_fault_handler)
+            .set_stack_index(gdt::DOUBLE_FAULT_IST_INDEX);
+    }
+    idt.invalid_tss.set_handler_fn(invalid_tss_handler);
+    idt.segment_not_present
+        .set_handler_fn(segment_not_present_handler);
+    idt.stack_segment_fault
+        .set_handler_fn(stack_segment_fault_handler);
+    idt.general_protection_fault
+        .set_handler_fn(general_protection_fault_handler);
+    idt.page_fault.set_handler_fn(page_fault_handler);
+    idt.x87_floating_point
+        .set_handler_fn(x87_floating_point_handler);
+    idt.alignment_check.set_handler_fn(alignment_check_handler);
+    idt.machine_check.set_handler_fn(machine_check_handler);
+    idt.simd_floating_point
+        .set_handler_fn(simd_floating_point_handler);
+    idt.virtualization.set_handler_fn(virtualization_handler);
+    idt.vmm_communication_exception
+        .set_handler_fn(vmm_communication_exception_handler);
+    idt.security_exception
+        .set_handler_fn(security_exception_handler);
+}
+
+/// Prints the exception name, the error code and the stack frame to both VGA and serial
+fn report(
+    vector: u8,
+    name: &str,
+    error_code: Option<&dyn fmt::Debug>,
+    stack_frame: &InterruptStackFrame,
+) {
+    println!("EXCEPTION: {name} (vector {vector})");
+    serial_println!("EXCEPTION: {} (vector {})", name, vector);
+    if let Some(error_code) = error_code {
+        println!("Error code: {error_code:?}");
+        serial_println!("Error code: {:?}", error_code);
+    }
+    println!("{stack_frame:#?}");
+    serial_println!("{:#?}", stack_frame);
+}
+
+/// Generates a handler which reports the exception and continues execution
+macro_rules! trap_handler {
+    ($handler:ident, $vector:literal, $name:literal) => {
+        extern "x86-interrupt" fn $handler(stack_frame: InterruptStackFrame) {
+            report($vector, $name, None, &stack_frame);
+        }
+    };
+}
+
+/// Generates a handler which reports the exception and panics
+///
+/// The panic message is always `EXCEPTION: <name>`, so tests can check which handler ran.
+macro_rules! fault_handler {
+    ($handler:ident, $vector:literal, $name:literal) => {
+        extern "x86-interrupt" fn $handler(stack_frame: InterruptStackFrame) {
+            report($vector, $name, None, &stack_frame);
+            panic!(concat!("EXCEPTION: ", $name));
+        }
+    };
+    ($handler:ident, $vector:literal, $name:literal, raw) => {
+        extern "x86-interrupt" fn $handler(stack_frame: InterruptStackFrame, error_code: u64) {
+            report($vector, $name, Some(&error_code), &stack_frame);
+            panic!(concat!("EXCEPTION: ", $name));
+        }
+    };
+    ($handler:ident, $vector:literal, $name:literal, selector) => {
+        extern "x86-interrupt" fn $handler(stack_frame: InterruptStackFrame, error_code: u64) {
+            let error_code = SelectorErrorCode::new_truncate(error_code);
+            report($vector, $name, Some(&error_code), &stack_frame);
+            panic!(concat!("EXCEPTION: ", $name));
+        }
+    };
+}
+
+fault_handler!(divide_error_handler, 0, "DIVIDE ERROR");
+trap_handler!(debug_handler, 1, "DEBUG");
+trap_handler!(non_maskable_interrupt_handler, 2, "NON MASKABLE INTERRUPT");
+trap_handler!(breakpoint_handler, 3, "BREAKPOINT");
+trap_handler!(overflow_handler, 4, "OVERFLOW");
+fault_handler!(bound_range_exceeded_handler, 5, "BOUND RANGE EXCEEDED");
+fault_handler!(invalid_opcode_handler, 6, "INVALID OPCODE");
+fault_handler!(device_not_available_handler, 7, "DEVICE NOT AVAILABLE");
+fault_handler!(invalid_tss_handler, 10, "INVALID TSS", selector);
+fault_handler!(
+    segment_not_present_handler,
+    11,
+    "SEGMENT NOT PRESENT",
+    selector
+);
+fault_handler!(
+    stack_segment_fault_handler,
+    12,
+    "STACK SEGMENT FAULT",
+    selector
+);
+fault_handler!(
+    general_protection_fault_handler,
+    13,
+    "GENERAL PROTECTION FAULT",
+    selector
+);
+fault_handler!(x87_floating_point_handler, 16, "X87 FLOATING POINT");
+fault_handler!(alignment_check_handler, 17, "ALIGNMENT CHECK", raw);
+fault_handler!(simd_floating_point_handler, 19, "SIMD FLOATING POINT");
+fault_handler!(virtualization_handler, 20, "VIRTUALIZATION");
+fault_handler!(
+    vmm_communication_exception_handler,
+    29,
+    "VMM COMMUNICATION EXCEPTION",
+    raw
+);
+fault_handler!(security_exception_handler, 30, "SECURITY EXCEPTION", raw);
+
+/// Handler for double fault exception
+extern "x86-interrupt" fn double_fault_handler(
+    stack_frame: InterruptStackFrame,
+    error_code: u64,
+) -> ! {
+    report(8, "DOUBLE FAULT", Some(&error_code), &stack_frame);
+    panic!("EXCEPTION: DOUBLE FAULT");
+}
+
+/// Handler for page fault exception
+extern "x86-interrupt" fn page_fault_handler(
+    stack_frame: InterruptStackFrame,
+    error_code: PageFaultErrorCode,
+) {
+    use x86_64::registers::control::Cr2;
+
+    report(14, "PAGE FAULT", Some(&error_code), &stack_frame);
+    println!("Accessed address: {:?}", Cr2::read());
+    serial_println!("Accessed address: {:?}", Cr2::read());
+    panic!("EXCEPTION: PAGE FAULT");
+}
+
+/// Handler for machine check exception
+extern "x86-interrupt" fn machine_check_handler(stack_frame: InterruptStackFrame) -> ! {
+    report(18, "MACHINE CHECK", None, &stack_frame);
+    panic!("EXCEPTION: MACHINE CHECK");
+}
@@ -4,17 +4,42 @@
 #![test_runner(crate::test_runner)]
 #![reexport_test_harness_main = "test_main"]
 #![feature(abi_x86_interrupt)]
-#![feature(type_alias_impl_trait)]
 #![deny(unsafe_op_in_unsafe_fn)]
 
+extern crate alloc;
+
+use core::sync::atomic::{AtomicBool, Ordering};
+
+/// Discovery of the machine topology through the ACPI tables
+pub mod acpi;
+/// Ring buffer keeping the recent kernel output
+pub mod dmesg;
+/// Linear framebuffer of the Bochs graphics adapter
+pub mod framebuffer;
 /// Handles the faults
 pub mod gdt;
+/// Lines, shapes and images drawn on the framebuffer
+pub mod graphics;
 /// Handles the hardware interrupts
 pub mod interrupts;
+/// Kernel logger behind the `log` facade
+pub mod logger;
 /// Helper module for memory management
 pub mod memory;
+/// Discovery of the devices on the PCI bus
+pub mod pci;
+/// CMOS real time clock and the wall clock time
+pub mod rtc;
 /// Handles printing to the serial console
 pub mod serial;
+/// Interactive shell reading commands from the keyboard and the serial port
+pub mod shell;
+/// Cooperative multitasking with async/await
+pub mod task;
+/// Preemptive kernel threads scheduled by the timer interrupt
+pub mod thread;
+/// Timer configuration and the monotonic clock
+pub mod time;
 /// Handles printing to the VGA buffer
 pub mod vga_buffer;
 
@@ -52,6 +77,44 @@ pub fn exit_qemu(exit_code: QemuExitCode) {
     }
 }
 
+/// Restarts the machine
+///
+/// Tries the reset register of the ACPI tables, then the keyboard controller, and
+/// triple faults if neither works.
+pub fn reboot() -> ! {
+    use x86_64::instructions::{port::Port, tables::lidt};
+    use x86_64::structures::DescriptorTablePointer;
+
+    /// Address space of the reset registers in the I/O ports
+    const ACPI_IO_SPACE: u8 = 1;
+    const KEYBOARD_CONTROLLER_COMMAND: u16 = 0x64;
+    /// Pulses the reset line of the CPU
+    const KEYBOARD_CONTROLLER_RESET: u8 = 0xFE;
+
+    x86_64::instructions::interrupts::disable();
+
+    let fadt = acpi::tables().and_then(|tables| tables.fadt.as_ref());
+    if let Some(fadt) = fadt {
+        if let Some(register) = fadt
+            .reset_register
+            .filter(|register| register.address_space == ACPI_IO_SPACE)
+        {
+            unsafe { Port::new(register.address as u16).write(fadt.reset_value) };
+        }
+    }
+
+    unsafe { Port::new(KEYBOARD_CONTROLLER_COMMAND).write(KEYBOARD_CONTROLLER_RESET) };
+
+    // Without an IDT the next exception can't be handled and resets the CPU
+    let empty = DescriptorTablePointer {
+        limit: 0,
+        base: x86_64::VirtAddr::zero(),
+    };
+    unsafe { lidt(&empty) };
+    x86_64::instructions::interrupts::int3();
+    hlt_loop();
+}
+
 /// Infinitelly halts the cpu to save energy
 pub fn hlt_loop() -> ! {
     loop {
@@ -59,6 +122,31 @@ pub fn hlt_loop() -> ! {
     }
 }
 
+/// Set by the first panic
+static PANICKING: AtomicBool = AtomicBool::new(false);
+
+/// Prepares the output for a panic handler, call it before printing anything
+///
+/// Disables interrupts and releases the screen, serial and kernel log locks, which
+/// the panicking code may still hold. Returns `false` if the kernel is already
+/// panicking, then the handler panicked itself and should stop without printing.
+pub fn begin_panic() -> bool {
+    x86_64::instructions::interrupts::disable();
+    if PANICKING.swap(true, Ordering::SeqCst) {
+        return false;
+    }
+
+    // Nothing runs after the panic handler, so the holders will never touch them again
+    unsafe {
+        vga_buffer::force_unlock();
+        serial::force_unlock();
+        dmesg::force_unlock();
+    }
+    // The message goes to the kernel console, which has to be on the screen
+    vga_buffer::console::switch(vga_buffer::console::KERNEL_CONSOLE);
+    true
+}
+
 /// A custom test runner
 pub fn test_runner(tests: &[&dyn Testable]) {
     serial_println!("Running {} tests", tests.len());
@@ -78,8 +166,18 @@ entry_point!(test_kernel_main);
 
 /// Entry point for `cargo test`
 #[cfg(test)]
-fn test_kernel_main(_boot_info: &'static BootInfo) -> ! {
+fn test_kernel_main(boot_info: &'static BootInfo) -> ! {
+    use memory::BitmapFrameAllocator;
+    use x86_64::VirtAddr;
+
     init();
+
+    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    let mut mapper = unsafe { memory::init(phys_mem_offset) };
+    let mut frame_allocator =
+        unsafe { BitmapFrameAllocator::init(&boot_info.memory_map, phys_mem_offset) };
+    memory::heap::init(&mut mapper, &mut frame_allocator).expect("Heap initialization failed");
+
     test_main();
     hlt_loop();
 }
@@ -93,16 +191,76 @@ fn panic(info: &core::panic::PanicInfo) -> ! {
 
 /// A panic handler for tests
 pub fn test_panic_handler(info: &core::panic::PanicInfo) -> ! {
+    if !begin_panic() {
+        exit_qemu(QemuExitCode::Failed);
+        hlt_loop();
+    }
     serial_println!("[failed]\n");
     serial_println!("Error: {}\n", info);
     exit_qemu(QemuExitCode::Failed);
     hlt_loop();
 }
 
+/// A panic handler for tests that expect the CPU exception `name` to be raised
+///
+/// The exception handlers panic with `EXCEPTION: <name>`, anything else is a failure.
+pub fn test_exception_panic_handler(info: &core::panic::PanicInfo, name: &str) -> ! {
+    let exception = info
+        .message()
+        .as_str()
+        .and_then(|message| message.strip_prefix("EXCEPTION: "));
+
+    if exception == Some(name) && begin_panic() {
+        serial_println!("[ok]");
+        exit_qemu(QemuExitCode::Success);
+        hlt_loop();
+    }
+
+    test_panic_handler(info)
+}
+
+/// Defines the entry point and the panic handler of a test expecting the CPU exception `name`
+///
+/// The entry point calls `trigger` after `init`, the test fails if it returns.
+#[macro_export]
+macro_rules! exception_test {
+    ($name:literal, $trigger:path) => {
+        #[no_mangle]
+        pub extern "C" fn _start() -> ! {
+            $crate::serial_print!("{}::{}...\t", module_path!(), module_path!());
+
+            $crate::init();
+            $trigger();
+
+            $crate::serial_println!("[exception not raised]");
+            $crate::exit_qemu($crate::QemuExitCode::Failed);
+            $crate::hlt_loop();
+        }
+
+        #[panic_handler]
+        fn panic(info: &core::panic::PanicInfo) -> ! {
+            $crate::test_exception_panic_handler(info, $name)
+        }
+    };
+}
+
+/// Returns the kernel command line
+///
+/// The bootloader doesn't pass one, so it is baked in from `RUDOS_CMDLINE` at build time.
+#[must_use]
+pub const fn command_line() -> &'static str {
+    match option_env!("RUDOS_CMDLINE") {
+        Some(command_line) => command_line,
+        None => "",
+    }
+}
+
 /// All inicializations needed for the OS happen here
 pub fn init() {
     gdt::init();
     interrupts::init_idt();
-    unsafe { interrupts::PICS.lock().initialize() };
+    interrupts::init_pics();
+    time::init(time::DEFAULT_FREQUENCY);
+    vga_buffer::enable_cursor();
     x86_64::instructions::interrupts::enable();
 }
@@ -0,0 +1,231 @@
+use crate::{dmesg, serial, time, vga_buffer};
+use core::fmt;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use log::{Level, LevelFilter, Log, Metadata, Record, SetLoggerError};
+
+/// Maximum number of per-module filters taken from the command line
+pub const MAX_FILTERS: usize = 16;
+/// Level used for the modules without a filter, unless the command line changes it
+pub const DEFAULT_LEVEL: LevelFilter = LevelFilter::Info;
+/// Records up to this level are also printed to the screen, unless the command line changes it
+pub const DEFAULT_VGA_LEVEL: LevelFilter = LevelFilter::Info;
+
+static LOGGER: KernelLogger = KernelLogger;
+/// The filters parsed from the command line by `init`
+static FILTERS: spin::Once<Filters> = spin::Once::new();
+/// The most verbose level printed to the screen, stored as `LevelFilter as usize`
+static VGA_LEVEL: AtomicUsize = AtomicUsize::new(DEFAULT_VGA_LEVEL as usize);
+
+/// A level for every module whose path starts with the prefix
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Directive {
+    /// `None` applies to every module
+    module: Option<&'static str>,
+    level: LevelFilter,
+}
+
+/// The directives of the `log=` option
+#[derive(Debug, Clone, Copy)]
+struct Filters {
+    directives: [Directive; MAX_FILTERS],
+    len: usize,
+}
+
+impl Filters {
+    /// Parses comma separated directives like `warn,rudos::time=trace,rudos::acpi=off`
+    ///
+    /// Invalid directives and the ones over `MAX_FILTERS` are ignored.
+    fn parse(spec: &'static str) -> Self {
+        let mut filters = Self {
+            directives: [Directive {
+                module: None,
+                level: DEFAULT_LEVEL,
+            }; MAX_FILTERS],
+            len: 1,
+        };
+
+        for directive in spec.split(',').filter(|directive| !directive.is_empty()) {
+            let parsed = match directive.split_once('=') {
+                Some((module, level)) => level.parse().ok().map(|level| Directive {
+                    module: Some(module),
+                    level,
+                }),
+                // A bare level sets the default, a bare module enables everything in it
+                None => match directive.parse() {
+                    Ok(level) => Some(Directive {
+                        module: None,
+                        level,
+                    }),
+                    Err(_) => Some(Directive {
+                        module: Some(directive),
+                        level: LevelFilter::Trace,
+                    }),
+                },
+            };
+
+            match parsed {
+                Some(Directive {
+                    module: None,
+                    level,
+                }) => filters.directives[0].level = level,
+                Some(directive) if filters.len < MAX_FILTERS => {
+                    filters.directives[filters.len] = directive;
+                    filters.len += 1;
+                }
+                _ => {}
+            }
+        }
+
+        filters
+    }
+
+    fn directives(&self) -> &[Directive] {
+        &self.directives[..self.len]
+    }
+
+    /// Returns the level of the directive with the longest module prefix matching `target`
+    fn level_for(&self, target: &str) -> LevelFilter {
+        let mut best = self.directives[0];
+        for directive in &self.directives()[1..] {
+            let Some(module) = directive.module else {
+                continue;
+            };
+            let matches = target
+                .strip_prefix(module)
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with("::"));
+            if matches && best.module.map_or(0, str::len) <= module.len() {
+                best = *directive;
+            }
+        }
+        best.level
+    }
+
+    /// The most verbose level of any directive
+    fn max_level(&self) -> LevelFilter {
+        self.directives()
+            .iter()
+            .map(|directive| directive.level)
+            .max()
+            .unwrap_or(DEFAULT_LEVEL)
+    }
+}
+
+/// Routes the `log` records to the serial port and the important ones to the screen
+struct KernelLogger;
+
+impl Log for KernelLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        let level = FILTERS.r#try().map_or(DEFAULT_LEVEL, |filters| {
+            filters.level_for(metadata.target())
+        });
+        metadata.level() <= level
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        let uptime = time::uptime();
+        let (seconds, micros) = (uptime.as_secs(), uptime.subsec_micros());
+        let level = record.level();
+        let target = record.target();
+        // The record goes to the kernel log once, no matter where it is printed
+        let write = |line: fmt::Arguments| {
+            dmesg::write_fmt(line);
+            serial::write_fmt(line);
+            if level <= vga_level() {
+                vga_buffer::write_fmt(line);
+            }
+        };
+        // Both the screen and a serial terminal understand the color escapes
+        write(format_args!(
+            "[{seconds:5}.{micros:06}] \x1b[{}m{level:5}\x1b[0m {target}: {}\n",
+            level_color(level),
+            record.args()
+        ));
+    }
+
+    fn flush(&self) {}
+}
+
+/// SGR color parameter the level is printed in
+const fn level_color(level: Level) -> u8 {
+    match level {
+        Level::Error => 91,
+        Level::Warn => 93,
+        Level::Info => 32,
+        Level::Debug => 36,
+        Level::Trace => 90,
+    }
+}
+
+/// Installs the kernel logger with the filters of the kernel command line
+///
+/// The command line understands `log=<directives>`, where the directives are
+/// comma separated levels for every module (`warn`) or a module path and its
+/// subpaths (`rudos::time=trace`), and `log_vga=<level>` for the screen output.
+///
+/// # Errors
+///
+/// Fails if a logger was already installed.
+pub fn init(command_line: &'static str) -> Result<(), SetLoggerError> {
+    let mut spec = "";
+    for option in command_line.split_whitespace() {
+        if let Some(directives) = option.strip_prefix("log=") {
+            spec = directives;
+        } else if let Some(level) = option.strip_prefix("log_vga=") {
+            if let Ok(level) = level.parse() {
+                set_vga_level(level);
+            }
+        }
+    }
+
+    let filters = FILTERS.call_once(|| Filters::parse(spec));
+    log::set_logger(&LOGGER)?;
+    log::set_max_level(filters.max_level());
+    Ok(())
+}
+
+/// Returns the most verbose level that is printed to the screen
+#[must_use]
+pub fn vga_level() -> LevelFilter {
+    match VGA_LEVEL.load(Ordering::Relaxed) {
+        0 => LevelFilter::Off,
+        1 => LevelFilter::Error,
+        2 => LevelFilter::Warn,
+        3 => LevelFilter::Info,
+        4 => LevelFilter::Debug,
+        _ => LevelFilter::Trace,
+    }
+}
+
+/// Changes the most verbose level that is printed to the screen, serial gets everything
+pub fn set_vga_level(level: LevelFilter) {
+    VGA_LEVEL.store(level as usize, Ordering::Relaxed);
+}
+
+#[test_case]
+fn test_parse_filters() {
+    let filters = Filters::parse("warn,rudos::time=trace,rudos::acpi=off,rudos::time::hpet");
+    assert_eq!(filters.level_for("rudos::vga_buffer"), LevelFilter::Warn);
+    assert_eq!(filters.level_for("rudos::time"), LevelFilter::Trace);
+    assert_eq!(filters.level_for("rudos::time::wheel"), LevelFilter::Trace);
+    assert_eq!(filters.level_for("rudos::timer"), LevelFilter::Warn);
+    assert_eq!(filters.level_for("rudos::acpi"), LevelFilter::Off);
+    assert_eq!(filters.max_level(), LevelFilter::Trace);
+}
+
+#[test_case]
+fn test_invalid_filters_are_ignored() {
+    let filters = Filters::parse("rudos::time=loud,,");
+    assert_eq!(filters.directives().len(), 1);
+    assert_eq!(filters.level_for("rudos::time"), DEFAULT_LEVEL);
+}
+
+#[test_case]
+fn test_log_does_not_panic() {
+    let _ = init("");
+    log::info!("test_log_does_not_panic output");
+    assert!(log::log_enabled!(log::Level::Error));
+}
@@ -10,22 +10,74 @@ use rudos::println;
 entry_point!(kernel_main);
 
 /// This is the typechecked entry point of our system
-fn kernel_main(_boot_info: &'static BootInfo) -> ! {
+fn kernel_main(boot_info: &'static BootInfo) -> ! {
+    use rudos::interrupts::{self, InterruptController};
+    use rudos::memory::{self, BitmapFrameAllocator};
+    use rudos::task::{executor::Executor, Task};
+    use x86_64::VirtAddr;
+
     println!("Hello World{}", "!");
     rudos::init();
+    rudos::logger::init(rudos::command_line()).expect("Installing the logger failed");
+
+    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    let mut mapper = unsafe { memory::init(phys_mem_offset) };
+    let mut frame_allocator =
+        unsafe { BitmapFrameAllocator::init(&boot_info.memory_map, phys_mem_offset) };
+    memory::heap::init(&mut mapper, &mut frame_allocator).expect("Heap initialization failed");
+    rudos::thread::init();
+
+    match unsafe { rudos::acpi::init(phys_mem_offset) } {
+        Ok(tables) => {
+            if let Some(madt) = &tables.madt {
+                log::info!("CPUs: {}", madt.processors.len());
+            }
+        }
+        Err(error) => log::warn!("Reading the ACPI tables failed: {error:?}"),
+    }
+
+    if let Some((width, height)) = rudos::framebuffer::mode_from_command_line(rudos::command_line())
+    {
+        match rudos::framebuffer::init(width, height, &mut mapper, &mut frame_allocator) {
+            Ok(()) => log::info!("Framebuffer: {width}x{height}"),
+            Err(error) => log::warn!("Switching to the framebuffer failed: {error:?}"),
+        }
+    }
+
+    let clock = rudos::time::init_high_resolution(&mut mapper, &mut frame_allocator);
+    log::info!("Clock source: {clock:?}");
+    rudos::rtc::init();
+    log::info!("Date: {}", rudos::rtc::SystemTime::now().date_time());
+
+    match interrupts::select_controller(
+        InterruptController::Apic,
+        &mut mapper,
+        &mut frame_allocator,
+    ) {
+        Ok(controller) => log::info!("Interrupt controller: {controller:?}"),
+        Err(error) => log::warn!("Switching to the APIC failed, keeping the PICs: {error:?}"),
+    }
 
     // We need to manually call this because we are in no_main project
     #[cfg(test)]
     test_main();
 
-    rudos::hlt_loop();
+    let mut executor = Executor::new();
+    executor.spawn(Task::new(rudos::shell::run()));
+    executor.run();
 }
 
 /// This is the panic handler
 #[cfg(not(test))]
 #[panic_handler]
 fn panic(info: &core::panic::PanicInfo) -> ! {
+    // A panic while panicking, printing again could just panic again
+    if !rudos::begin_panic() {
+        rudos::hlt_loop();
+    }
     println!("{info}");
+    // The screen may have lost most of the output, the serial gets all of it
+    rudos::dmesg::dump_to_serial();
     rudos::hlt_loop();
 }
 
@@ -1,9 +1,16 @@
 use bootloader::bootinfo::{MemoryMap, MemoryRegionType};
+use core::sync::atomic::{AtomicU64, Ordering};
 use x86_64::{
-    structures::paging::{FrameAllocator, OffsetPageTable, PageTable, PhysFrame, Size4KiB},
+    structures::paging::{
+        mapper::MapToError, FrameAllocator, FrameDeallocator, Mapper, OffsetPageTable, Page,
+        PageSize, PageTable, PageTableFlags, PhysFrame, Size2MiB, Size4KiB,
+    },
     PhysAddr, VirtAddr,
 };
 
+/// The kernel heap and its global allocator
+pub mod heap;
+
 /// Initializes a new [OffsetPageTable](https://docs.rs/x86_64/latest/x86_64/structures/paging/mapper/struct.OffsetPageTable.html).
 ///
 /// # Safety
@@ -22,6 +29,49 @@ pub unsafe fn init(physical_memory_offset: VirtAddr) -> OffsetPageTable<'static>
     }
 }
 
+/// Virtual address where the device memory mappings start
+pub const MMIO_START: u64 = 0x_5555_0000_0000;
+
+/// Next free virtual address in the device memory region
+static NEXT_MMIO: AtomicU64 = AtomicU64::new(MMIO_START);
+
+/// Maps `size` bytes of device memory at the physical `address` as uncached memory.
+///
+/// Returns the virtual address corresponding to `address`.
+///
+/// # Errors
+///
+/// Returns an error if any of the pages could not be mapped.
+///
+/// # Safety
+///
+/// The caller must guarantee that the physical range belongs to a device and isn't
+/// used as regular memory, otherwise the new mapping would alias it.
+pub unsafe fn map_mmio(
+    address: PhysAddr,
+    size: u64,
+    mapper: &mut impl Mapper<Size4KiB>,
+    frame_allocator: &mut impl FrameAllocator<Size4KiB>,
+) -> Result<VirtAddr, MapToError<Size4KiB>> {
+    let start_frame = PhysFrame::<Size4KiB>::containing_address(address);
+    let end_frame = PhysFrame::containing_address(address + (size.max(1) - 1));
+    let frames = PhysFrame::range_inclusive(start_frame, end_frame);
+
+    let mapping_size = end_frame.start_address() - start_frame.start_address() + FRAME_SIZE;
+    let mapping_start = VirtAddr::new(NEXT_MMIO.fetch_add(mapping_size, Ordering::Relaxed));
+
+    let flags = PageTableFlags::PRESENT
+        | PageTableFlags::WRITABLE
+        | PageTableFlags::NO_CACHE
+        | PageTableFlags::WRITE_THROUGH;
+    for (page_index, frame) in frames.enumerate() {
+        let page = Page::containing_address(mapping_start + page_index as u64 * FRAME_SIZE);
+        unsafe { mapper.map_to(page, frame, flags, frame_allocator)?.flush() };
+    }
+
+    Ok(mapping_start + (address - start_frame.start_address()))
+}
+
 #[must_use]
 unsafe fn active_level4_table(physical_memory_offset: VirtAddr) -> &'static mut PageTable {
     use x86_64::registers::control::Cr3;
@@ -35,44 +85,186 @@ unsafe fn active_level4_table(physical_memory_offset: VirtAddr) -> &'static mut
     unsafe { &mut *page_table_ptr }
 }
 
-/// A temporary type so we can make a field in `BootInfoFrameAllocator` without known type
-type UsableFrames = impl Iterator<Item = PhysFrame>;
-/// A `FrameAllocator` that returns usable frames form bootloader's memory map.
-pub struct BootInfoFrameAllocator {
-    memory_map: &'static MemoryMap,
-    usable_frames: UsableFrames,
+/// Size of a regular frame in bytes
+const FRAME_SIZE: u64 = 4096;
+/// Number of regular frames making up one 2MiB frame
+const FRAMES_PER_HUGE_FRAME: usize = 512;
+/// Number of bitmap words covering one 2MiB frame
+const WORDS_PER_HUGE_FRAME: usize = FRAMES_PER_HUGE_FRAME / u64::BITS as usize;
+
+/// A `FrameAllocator` that keeps track of usable frames form bootloader's memory map in a bitmap.
+///
+/// A set bit means the frame is used. Frames outside of the usable regions
+/// are marked as used from the start, so they are never handed out.
+pub struct BitmapFrameAllocator {
+    bitmap: &'static mut [u64],
+    /// Word where the search for a free frame starts
+    next_word: usize,
+    usable_frames: usize,
+    free_frames: usize,
 }
 
-impl BootInfoFrameAllocator {
+impl BitmapFrameAllocator {
     /// Create a `FrameAllocator` from the passed map.
     ///
+    /// The bitmap is stored in the first usable region big enough to hold it
+    /// and the frames it occupies are marked as used.
+    ///
     /// # Safety
     /// The caller must guarantee that the passed `memory_map` is valid.
     /// Meaning all the frames marked as `USABLE` in specified `memory_map` must be unused.
+    /// The complete physical memory also has to be mapped at `physical_memory_offset`.
+    ///
+    /// # Panics
+    /// Panics if there is no usable region big enough to hold the bitmap.
     #[must_use]
-    pub unsafe fn init(memory_map: &'static MemoryMap) -> Self {
-        let frame_addresses = memory_map
-            .iter()
-            // Filter only usable regions
-            .filter(|r| r.region_type == MemoryRegionType::Usable)
-            // Get the address ranges
-            .map(|r| r.range.start_addr()..r.range.end_addr())
-            // Get the frame start addresses
-            .flat_map(|r| r.step_by(4096));
-
-        // Create `PhysFrame` from the start addresses
-        let usable_frames =
-            frame_addresses.map(|addr| PhysFrame::containing_address(PhysAddr::new(addr)));
-
-        Self {
-            memory_map,
-            usable_frames,
+    pub unsafe fn init(memory_map: &'static MemoryMap, physical_memory_offset: VirtAddr) -> Self {
+        let usable_regions = || {
+            memory_map
+                .iter()
+                .filter(|r| r.region_type == MemoryRegionType::Usable)
+        };
+
+        // We only need to track frames up to the end of the last usable region
+        let frame_count = usable_regions()
+            .map(|r| r.range.end_frame_number)
+            .max()
+            .unwrap_or(0);
+        let word_count = frame_count.div_ceil(u64::from(u64::BITS));
+        let bitmap_size = word_count * core::mem::size_of::<u64>() as u64;
+
+        let bitmap_region = usable_regions()
+            .find(|r| r.range.end_addr() - r.range.start_addr() >= bitmap_size)
+            .expect("No usable region is big enough for the frame bitmap");
+        let bitmap_start = physical_memory_offset + bitmap_region.range.start_addr();
+        let bitmap = unsafe {
+            core::slice::from_raw_parts_mut(bitmap_start.as_mut_ptr::<u64>(), word_count as usize)
+        };
+        // Everything is used until the memory map says otherwise
+        bitmap.fill(u64::MAX);
+
+        let mut allocator = Self {
+            bitmap,
+            next_word: 0,
+            usable_frames: 0,
+            free_frames: 0,
+        };
+
+        for region in usable_regions() {
+            for frame in region.range.start_frame_number..region.range.end_frame_number {
+                allocator.mark_free(frame as usize);
+            }
+        }
+        allocator.usable_frames = allocator.free_frames;
+
+        // The bitmap can't hand out the frames it lives in
+        let bitmap_first_frame = bitmap_region.range.start_frame_number;
+        let bitmap_frames = bitmap_size.div_ceil(FRAME_SIZE);
+        for frame in bitmap_first_frame..bitmap_first_frame + bitmap_frames {
+            allocator.mark_used(frame as usize);
         }
+
+        allocator
+    }
+
+    /// Number of frames that can be allocated right now
+    #[must_use]
+    pub const fn free_frames(&self) -> usize {
+        self.free_frames
+    }
+
+    /// Number of usable frames that are currently allocated (including the bitmap itself)
+    #[must_use]
+    pub const fn used_frames(&self) -> usize {
+        self.usable_frames - self.free_frames
+    }
+
+    /// Number of frames marked as usable in the memory map
+    #[must_use]
+    pub const fn usable_frames(&self) -> usize {
+        self.usable_frames
+    }
+
+    fn is_used(&self, index: usize) -> bool {
+        self.bitmap[index / 64] & (1 << (index % 64)) != 0
+    }
+
+    fn mark_used(&mut self, index: usize) {
+        assert!(!self.is_used(index), "Frame {index} is already used");
+        self.bitmap[index / 64] |= 1 << (index % 64);
+        self.free_frames -= 1;
+    }
+
+    fn mark_free(&mut self, index: usize) {
+        assert!(self.is_used(index), "Frame {index} is already free");
+        self.bitmap[index / 64] &= !(1 << (index % 64));
+        self.free_frames += 1;
+    }
+
+    /// Returns the bitmap index of the frame, panics if the frame isn't tracked
+    fn index_of<S: PageSize>(&self, frame: PhysFrame<S>) -> usize {
+        let index = (frame.start_address().as_u64() / FRAME_SIZE) as usize;
+        assert!(
+            index < self.bitmap.len() * 64,
+            "Frame {frame:?} isn't managed by the allocator"
+        );
+        index
     }
 }
 
-unsafe impl FrameAllocator<Size4KiB> for BootInfoFrameAllocator {
+unsafe impl FrameAllocator<Size4KiB> for BitmapFrameAllocator {
     fn allocate_frame(&mut self) -> Option<PhysFrame<Size4KiB>> {
-        self.usable_frames.next()
+        let word = (self.next_word..self.bitmap.len())
+            .chain(0..self.next_word)
+            .find(|&word| self.bitmap[word] != u64::MAX)?;
+        let index = word * 64 + self.bitmap[word].trailing_ones() as usize;
+
+        self.next_word = word;
+        self.mark_used(index);
+
+        Some(PhysFrame::containing_address(PhysAddr::new(
+            index as u64 * FRAME_SIZE,
+        )))
+    }
+}
+
+impl FrameDeallocator<Size4KiB> for BitmapFrameAllocator {
+    unsafe fn deallocate_frame(&mut self, frame: PhysFrame<Size4KiB>) {
+        let index = self.index_of(frame);
+
+        self.mark_free(index);
+        self.next_word = self.next_word.min(index / 64);
+    }
+}
+
+unsafe impl FrameAllocator<Size2MiB> for BitmapFrameAllocator {
+    fn allocate_frame(&mut self) -> Option<PhysFrame<Size2MiB>> {
+        // 2MiB frames are aligned, so they always cover whole bitmap words
+        let chunk = self
+            .bitmap
+            .chunks_exact(WORDS_PER_HUGE_FRAME)
+            .position(|words| words.iter().all(|&word| word == 0))?;
+
+        self.bitmap[chunk * WORDS_PER_HUGE_FRAME..][..WORDS_PER_HUGE_FRAME].fill(u64::MAX);
+        self.free_frames -= FRAMES_PER_HUGE_FRAME;
+
+        Some(PhysFrame::containing_address(PhysAddr::new(
+            (chunk * FRAMES_PER_HUGE_FRAME) as u64 * FRAME_SIZE,
+        )))
+    }
+}
+
+impl FrameDeallocator<Size2MiB> for BitmapFrameAllocator {
+    unsafe fn deallocate_frame(&mut self, frame: PhysFrame<Size2MiB>) {
+        let first_word = self.index_of(frame) / 64;
+        let words = &mut self.bitmap[first_word..][..WORDS_PER_HUGE_FRAME];
+
+        assert!(
+            words.iter().all(|&word| word == u64::MAX),
+            "Frame {frame:?} is not fully used"
+        );
+        words.fill(0);
+        self.free_frames += FRAMES_PER_HUGE_FRAME;
+        self.next_word = self.next_word.min(first_word);
     }
 }
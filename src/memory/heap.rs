@@ -0,0 +1,142 @@
+use core::alloc::{GlobalAlloc, Layout};
+use core::sync::atomic::{AtomicUsize, Ordering};
+use x86_64::{
+    structures::paging::{
+        mapper::MapToError, FrameAllocator, Mapper, Page, PageTableFlags, Size4KiB,
+    },
+    VirtAddr,
+};
+
+/// A simple allocator that never frees memory until all allocations are freed
+pub mod bump;
+/// An allocator with lists of blocks of fixed sizes
+pub mod fixed_size_block;
+/// An allocator that uses the freed memory regions as a linked list
+pub mod linked_list;
+
+#[cfg(not(any(
+    feature = "bump-allocator",
+    feature = "linked-list-allocator",
+    feature = "fixed-size-block-allocator"
+)))]
+compile_error!("One of the heap allocator features has to be enabled");
+
+#[cfg(any(
+    all(feature = "bump-allocator", feature = "linked-list-allocator"),
+    all(feature = "bump-allocator", feature = "fixed-size-block-allocator"),
+    all(
+        feature = "linked-list-allocator",
+        feature = "fixed-size-block-allocator"
+    ),
+))]
+compile_error!("Only one heap allocator feature can be enabled, use `--no-default-features`");
+
+/// Virtual address where the kernel heap starts
+pub const HEAP_START: usize = 0x_4444_4444_0000;
+/// Size of the kernel heap in bytes
+pub const HEAP_SIZE: usize = 1024 * 1024;
+
+#[cfg(feature = "bump-allocator")]
+type Allocator = bump::BumpAllocator;
+#[cfg(feature = "linked-list-allocator")]
+type Allocator = linked_list::LinkedListAllocator;
+#[cfg(feature = "fixed-size-block-allocator")]
+type Allocator = fixed_size_block::FixedSizeBlockAllocator;
+
+#[global_allocator]
+static ALLOCATOR: Counted<Locked<Allocator>> = Counted::new(Locked::new(Allocator::new()));
+
+/// Maps the heap region and hands it over to the global allocator
+///
+/// # Errors
+///
+/// Returns an error if any of the heap pages could not be mapped.
+pub fn init(
+    mapper: &mut impl Mapper<Size4KiB>,
+    frame_allocator: &mut impl FrameAllocator<Size4KiB>,
+) -> Result<(), MapToError<Size4KiB>> {
+    let page_range = {
+        let heap_start = VirtAddr::new(HEAP_START as u64);
+        let heap_end = heap_start + HEAP_SIZE - 1u64;
+        let heap_start_page = Page::containing_address(heap_start);
+        let heap_end_page = Page::containing_address(heap_end);
+        Page::range_inclusive(heap_start_page, heap_end_page)
+    };
+
+    for page in page_range {
+        let frame = frame_allocator
+            .allocate_frame()
+            .ok_or(MapToError::FrameAllocationFailed)?;
+        let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
+        unsafe { mapper.map_to(page, frame, flags, frame_allocator)?.flush() };
+    }
+
+    unsafe { ALLOCATOR.inner.lock().init(HEAP_START, HEAP_SIZE) };
+
+    Ok(())
+}
+
+/// Number of bytes the live allocations asked for
+///
+/// The allocator needs more than that, for alignment and its own bookkeeping.
+#[must_use]
+pub fn used() -> usize {
+    ALLOCATOR.used.load(Ordering::Relaxed)
+}
+
+/// Counts the bytes allocated through the inner allocator
+struct Counted<A> {
+    inner: A,
+    used: AtomicUsize,
+}
+
+impl<A> Counted<A> {
+    const fn new(inner: A) -> Self {
+        Self {
+            inner,
+            used: AtomicUsize::new(0),
+        }
+    }
+}
+
+unsafe impl<A: GlobalAlloc> GlobalAlloc for Counted<A> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = unsafe { self.inner.alloc(layout) };
+        if !ptr.is_null() {
+            self.used.fetch_add(layout.size(), Ordering::Relaxed);
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { self.inner.dealloc(ptr, layout) };
+        self.used.fetch_sub(layout.size(), Ordering::Relaxed);
+    }
+}
+
+/// A wrapper around `spin::Mutex` so we can implement `GlobalAlloc` for our allocators
+///
+/// The `GlobalAlloc` implementations hold the lock with interrupts disabled, so a preempted
+/// thread never keeps the heap locked and interrupt handlers may allocate.
+pub struct Locked<A> {
+    inner: spin::Mutex<A>,
+}
+
+impl<A> Locked<A> {
+    /// Wraps the allocator
+    pub const fn new(inner: A) -> Self {
+        Self {
+            inner: spin::Mutex::new(inner),
+        }
+    }
+
+    /// Locks the inner allocator
+    pub fn lock(&self) -> spin::MutexGuard<'_, A> {
+        self.inner.lock()
+    }
+}
+
+/// Aligns the address `addr` upwards to `align`, which has to be a power of two
+const fn align_up(addr: usize, align: usize) -> usize {
+    (addr + align - 1) & !(align - 1)
+}
@@ -0,0 +1,77 @@
+use super::{align_up, Locked};
+use core::alloc::{GlobalAlloc, Layout};
+use core::ptr;
+use x86_64::instructions::interrupts::without_interrupts;
+
+/// Allocates memory linearly and only reuses it once every allocation is freed
+pub struct BumpAllocator {
+    heap_start: usize,
+    heap_end: usize,
+    next: usize,
+    allocations: usize,
+}
+
+impl BumpAllocator {
+    /// Creates a new empty `BumpAllocator`
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            heap_start: 0,
+            heap_end: 0,
+            next: 0,
+            allocations: 0,
+        }
+    }
+
+    /// Initializes the allocator with the given heap bounds
+    ///
+    /// # Safety
+    ///
+    /// The caller must guarantee that the given memory range is unused
+    /// and mapped. This method must be called only once.
+    pub unsafe fn init(&mut self, heap_start: usize, heap_size: usize) {
+        self.heap_start = heap_start;
+        self.heap_end = heap_start + heap_size;
+        self.next = heap_start;
+    }
+}
+
+impl Default for BumpAllocator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+unsafe impl GlobalAlloc for Locked<BumpAllocator> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        without_interrupts(|| {
+            let mut bump = self.lock();
+
+            let alloc_start = align_up(bump.next, layout.align());
+            let Some(alloc_end) = alloc_start.checked_add(layout.size()) else {
+                return ptr::null_mut();
+            };
+
+            if alloc_end > bump.heap_end {
+                // Out of memory
+                ptr::null_mut()
+            } else {
+                bump.next = alloc_end;
+                bump.allocations += 1;
+                alloc_start as *mut u8
+            }
+        })
+    }
+
+    unsafe fn dealloc(&self, _ptr: *mut u8, _layout: Layout) {
+        without_interrupts(|| {
+            let mut bump = self.lock();
+
+            bump.allocations -= 1;
+            // Only when everything is freed we can start from the beginning again
+            if bump.allocations == 0 {
+                bump.next = bump.heap_start;
+            }
+        });
+    }
+}
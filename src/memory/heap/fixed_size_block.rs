@@ -0,0 +1,109 @@
+use super::linked_list::LinkedListAllocator;
+use super::Locked;
+use core::alloc::{GlobalAlloc, Layout};
+use core::{mem, ptr};
+use x86_64::instructions::interrupts::without_interrupts;
+
+/// The block sizes to use
+///
+/// The sizes must each be power of 2 because they are also used as
+/// the block alignment (alignments must be always powers of 2).
+const BLOCK_SIZES: &[usize] = &[8, 16, 32, 64, 128, 256, 512, 1024, 2048];
+
+/// A freed block, stored directly inside of that block
+struct ListNode {
+    next: Option<&'static mut ListNode>,
+}
+
+/// Keeps a list of freed blocks for each of the `BLOCK_SIZES`
+///
+/// Allocations bigger than the biggest block go directly to the fallback allocator.
+pub struct FixedSizeBlockAllocator {
+    list_heads: [Option<&'static mut ListNode>; BLOCK_SIZES.len()],
+    fallback_allocator: LinkedListAllocator,
+}
+
+impl FixedSizeBlockAllocator {
+    /// Creates a new empty `FixedSizeBlockAllocator`
+    #[must_use]
+    pub const fn new() -> Self {
+        const EMPTY: Option<&'static mut ListNode> = None;
+        Self {
+            list_heads: [EMPTY; BLOCK_SIZES.len()],
+            fallback_allocator: LinkedListAllocator::new(),
+        }
+    }
+
+    /// Initializes the allocator with the given heap bounds
+    ///
+    /// # Safety
+    ///
+    /// The caller must guarantee that the given memory range is unused
+    /// and mapped. This method must be called only once.
+    pub unsafe fn init(&mut self, heap_start: usize, heap_size: usize) {
+        unsafe { self.fallback_allocator.init(heap_start, heap_size) };
+    }
+}
+
+impl Default for FixedSizeBlockAllocator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Chooses an appropriate block size for the given layout
+///
+/// Returns an index into the `BLOCK_SIZES` array.
+fn list_index(layout: &Layout) -> Option<usize> {
+    let required_block_size = layout.size().max(layout.align());
+    BLOCK_SIZES.iter().position(|&s| s >= required_block_size)
+}
+
+unsafe impl GlobalAlloc for Locked<FixedSizeBlockAllocator> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        without_interrupts(|| {
+            let mut allocator = self.lock();
+
+            let Some(index) = list_index(&layout) else {
+                return allocator.fallback_allocator.allocate(layout);
+            };
+
+            if let Some(node) = allocator.list_heads[index].take() {
+                allocator.list_heads[index] = node.next.take();
+                return (node as *mut ListNode).cast::<u8>();
+            }
+
+            // No block exists in list, so we allocate a new one
+            let block_size = BLOCK_SIZES[index];
+            let block_align = block_size;
+            let Ok(layout) = Layout::from_size_align(block_size, block_align) else {
+                return ptr::null_mut();
+            };
+            allocator.fallback_allocator.allocate(layout)
+        })
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        without_interrupts(|| {
+            let mut allocator = self.lock();
+
+            let Some(index) = list_index(&layout) else {
+                unsafe { allocator.fallback_allocator.deallocate(ptr, layout) };
+                return;
+            };
+
+            // The block has to be able to hold a `ListNode`
+            assert!(mem::size_of::<ListNode>() <= BLOCK_SIZES[index]);
+            assert!(mem::align_of::<ListNode>() <= BLOCK_SIZES[index]);
+
+            let new_node = ListNode {
+                next: allocator.list_heads[index].take(),
+            };
+            let new_node_ptr = ptr.cast::<ListNode>();
+            unsafe {
+                new_node_ptr.write(new_node);
+                allocator.list_heads[index] = Some(&mut *new_node_ptr);
+            }
+        });
+    }
+}
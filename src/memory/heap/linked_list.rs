@@ -0,0 +1,152 @@
+use super::{align_up, Locked};
+use core::alloc::{GlobalAlloc, Layout};
+use core::{mem, ptr};
+use x86_64::instructions::interrupts::without_interrupts;
+
+/// A free memory region, stored directly inside of that region
+struct ListNode {
+    size: usize,
+    next: Option<&'static mut ListNode>,
+}
+
+impl ListNode {
+    const fn new(size: usize) -> Self {
+        Self { size, next: None }
+    }
+
+    fn start_addr(&self) -> usize {
+        self as *const Self as usize
+    }
+
+    fn end_addr(&self) -> usize {
+        self.start_addr() + self.size
+    }
+}
+
+/// Keeps the freed memory regions in a linked list and hands out the first one that fits
+pub struct LinkedListAllocator {
+    head: ListNode,
+}
+
+impl LinkedListAllocator {
+    /// Creates a new empty `LinkedListAllocator`
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            head: ListNode::new(0),
+        }
+    }
+
+    /// Initializes the allocator with the given heap bounds
+    ///
+    /// # Safety
+    ///
+    /// The caller must guarantee that the given memory range is unused
+    /// and mapped. This method must be called only once.
+    pub unsafe fn init(&mut self, heap_start: usize, heap_size: usize) {
+        unsafe { self.add_free_region(heap_start, heap_size) };
+    }
+
+    /// Allocates a region for the `layout`, returns null pointer when out of memory
+    pub fn allocate(&mut self, layout: Layout) -> *mut u8 {
+        let (size, align) = Self::size_align(layout);
+
+        if let Some((region, alloc_start)) = self.find_region(size, align) {
+            let alloc_end = alloc_start + size;
+            let excess_size = region.end_addr() - alloc_end;
+            if excess_size > 0 {
+                unsafe { self.add_free_region(alloc_end, excess_size) };
+            }
+            alloc_start as *mut u8
+        } else {
+            ptr::null_mut()
+        }
+    }
+
+    /// Gives the region back to the allocator
+    ///
+    /// # Safety
+    ///
+    /// The `ptr` must have been returned by `allocate` with the same `layout`.
+    pub unsafe fn deallocate(&mut self, ptr: *mut u8, layout: Layout) {
+        let (size, _) = Self::size_align(layout);
+        unsafe { self.add_free_region(ptr as usize, size) };
+    }
+
+    /// Adds the region to the front of the list
+    unsafe fn add_free_region(&mut self, addr: usize, size: usize) {
+        // The freed region has to be able to hold a `ListNode`
+        assert_eq!(align_up(addr, mem::align_of::<ListNode>()), addr);
+        assert!(size >= mem::size_of::<ListNode>());
+
+        let mut node = ListNode::new(size);
+        node.next = self.head.next.take();
+        let node_ptr = addr as *mut ListNode;
+        unsafe {
+            node_ptr.write(node);
+            self.head.next = Some(&mut *node_ptr);
+        }
+    }
+
+    /// Removes and returns the first region big enough for the allocation
+    fn find_region(&mut self, size: usize, align: usize) -> Option<(&'static mut ListNode, usize)> {
+        let mut current = &mut self.head;
+
+        while let Some(ref mut region) = current.next {
+            if let Ok(alloc_start) = Self::alloc_from_region(region, size, align) {
+                // Unlink the region from the list
+                let next = region.next.take();
+                let ret = Some((current.next.take()?, alloc_start));
+                current.next = next;
+                return ret;
+            }
+            current = current.next.as_mut()?;
+        }
+
+        None
+    }
+
+    /// Tries to fit the allocation into the region, returns the allocation start address
+    fn alloc_from_region(region: &ListNode, size: usize, align: usize) -> Result<usize, ()> {
+        let alloc_start = align_up(region.start_addr(), align);
+        let alloc_end = alloc_start.checked_add(size).ok_or(())?;
+
+        if alloc_end > region.end_addr() {
+            return Err(());
+        }
+
+        // The rest of the region has to fit a `ListNode`, or be empty
+        let excess_size = region.end_addr() - alloc_end;
+        if excess_size > 0 && excess_size < mem::size_of::<ListNode>() {
+            return Err(());
+        }
+
+        Ok(alloc_start)
+    }
+
+    /// Adjusts the layout so every allocated region can later hold a `ListNode`
+    fn size_align(layout: Layout) -> (usize, usize) {
+        let layout = layout
+            .align_to(mem::align_of::<ListNode>())
+            .expect("adjusting alignment failed")
+            .pad_to_align();
+        let size = layout.size().max(mem::size_of::<ListNode>());
+        (size, layout.align())
+    }
+}
+
+impl Default for LinkedListAllocator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+unsafe impl GlobalAlloc for Locked<LinkedListAllocator> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        without_interrupts(|| self.lock().allocate(layout))
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        without_interrupts(|| unsafe { self.lock().deallocate(ptr, layout) });
+    }
+}
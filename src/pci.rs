@@ -0,0 +1,116 @@
+use x86_64::instructions::port::Port;
+
+/// Ports of the configuration mechanism #1
+const CONFIG_ADDRESS: u16 = 0xCF8;
+const CONFIG_DATA: u16 = 0xCFC;
+/// Set in `CONFIG_ADDRESS` for the access to go to the configuration space
+const ENABLE: u32 = 1 << 31;
+
+/// Register offsets in the configuration space
+const VENDOR_DEVICE: u8 = 0x00;
+const HEADER_TYPE: u8 = 0x0C;
+const BAR0: u8 = 0x10;
+/// Vendor of the missing devices
+const NO_VENDOR: u16 = 0xFFFF;
+/// Set in the header type of devices with more than one function
+const MULTI_FUNCTION: u32 = 1 << 23;
+
+/// Set in a BAR that is in the I/O space instead of memory
+const BAR_IO: u32 = 1 << 0;
+/// The type bits of a memory BAR
+const BAR_TYPE_MASK: u32 = 0b110;
+const BAR_TYPE_64: u32 = 0b100;
+
+/// The address of a function of a PCI device
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Location {
+    pub bus: u8,
+    pub device: u8,
+    pub function: u8,
+}
+
+impl Location {
+    /// Reads the 32 bit register at `offset` of the configuration space
+    #[must_use]
+    pub fn read_config(self, offset: u8) -> u32 {
+        let address = ENABLE
+            | u32::from(self.bus) << 16
+            | u32::from(self.device) << 11
+            | u32::from(self.function) << 8
+            | u32::from(offset & 0xFC);
+
+        x86_64::instructions::interrupts::without_interrupts(|| unsafe {
+            Port::new(CONFIG_ADDRESS).write(address);
+            Port::new(CONFIG_DATA).read()
+        })
+    }
+
+    /// Returns the vendor and device ID, `None` if there is no such function
+    #[must_use]
+    pub fn ids(self) -> Option<(u16, u16)> {
+        let ids = self.read_config(VENDOR_DEVICE);
+        let vendor = ids as u16;
+        (vendor != NO_VENDOR).then_some((vendor, (ids >> 16) as u16))
+    }
+
+    /// Returns the physical address a memory BAR decodes, `None` for an I/O BAR
+    #[must_use]
+    pub fn memory_bar(self, index: u8) -> Option<u64> {
+        let offset = BAR0 + index * 4;
+        let bar = self.read_config(offset);
+        if bar & BAR_IO != 0 {
+            return None;
+        }
+
+        let low = u64::from(bar & !0xF);
+        if bar & BAR_TYPE_MASK == BAR_TYPE_64 {
+            let high = u64::from(self.read_config(offset + 4));
+            Some(high << 32 | low)
+        } else {
+            Some(low)
+        }
+    }
+}
+
+/// Returns the first function whose vendor and device ID match
+#[must_use]
+pub fn find_device(vendor: u16, device: u16) -> Option<Location> {
+    locations().find(|location| location.ids() == Some((vendor, device)))
+}
+
+/// Returns every present function on every bus
+pub fn locations() -> impl Iterator<Item = Location> {
+    (0..=255u8).flat_map(|bus| {
+        (0..32u8).flat_map(move |device| {
+            let first = Location {
+                bus,
+                device,
+                function: 0,
+            };
+            let functions = match first.ids() {
+                None => 0,
+                Some(_) if first.read_config(HEADER_TYPE) & MULTI_FUNCTION != 0 => 8,
+                Some(_) => 1,
+            };
+            (0..functions)
+                .map(move |function| Location {
+                    bus,
+                    device,
+                    function,
+                })
+                .filter(|location| location.ids().is_some())
+        })
+    })
+}
+
+#[test_case]
+fn test_host_bridge_is_present() {
+    // Every PC has a host bridge at 0:0.0
+    let host_bridge = Location {
+        bus: 0,
+        device: 0,
+        function: 0,
+    };
+    assert!(host_bridge.ids().is_some());
+    assert_eq!(locations().next(), Some(host_bridge));
+}
@@ -0,0 +1,384 @@
+use crate::interrupts::{self, IrqError};
+use crate::time::{self, Duration};
+use core::fmt;
+use core::sync::atomic::{AtomicU64, AtomicU8, Ordering};
+use x86_64::instructions::{interrupts::without_interrupts, port::Port};
+
+/// IRQ line of the RTC periodic interrupt
+pub const RTC_IRQ: u8 = 8;
+
+/// Selects the CMOS register, the top bit disables the NMIs
+const CMOS_ADDRESS: u16 = 0x70;
+const CMOS_DATA: u16 = 0x71;
+const NMI_DISABLE: u8 = 0x80;
+
+/// CMOS registers of the RTC
+const REGISTER_SECONDS: u8 = 0x00;
+const REGISTER_MINUTES: u8 = 0x02;
+const REGISTER_HOURS: u8 = 0x04;
+const REGISTER_DAY: u8 = 0x07;
+const REGISTER_MONTH: u8 = 0x08;
+const REGISTER_YEAR: u8 = 0x09;
+const REGISTER_STATUS_A: u8 = 0x0A;
+const REGISTER_STATUS_B: u8 = 0x0B;
+const REGISTER_STATUS_C: u8 = 0x0C;
+/// Read only, selected after every access to put the NMI bit back
+const REGISTER_STATUS_D: u8 = 0x0D;
+
+/// Set in status A while the RTC updates its registers
+const STATUS_A_UPDATE_IN_PROGRESS: u8 = 1 << 7;
+/// Set in status B when the values are binary instead of BCD
+const STATUS_B_BINARY: u8 = 1 << 2;
+/// Set in status B when the hours are in 24 hour format
+const STATUS_B_24_HOUR: u8 = 1 << 1;
+/// Enables the periodic interrupt in status B
+const STATUS_B_PERIODIC_INTERRUPT: u8 = 1 << 6;
+/// Marks the afternoon in the hours register in 12 hour format
+const HOUR_PM: u8 = 1 << 7;
+
+/// `NMI_DISABLE` while the NMIs should stay masked between the CMOS accesses
+static NMI_MASK: AtomicU8 = AtomicU8::new(0);
+/// Nanoseconds between the Unix epoch and the boot, zero until `init`
+static BOOT_TIME_NANOS: AtomicU64 = AtomicU64::new(0);
+/// Periodic interrupts received since they were enabled
+static PERIODIC_INTERRUPTS: AtomicU64 = AtomicU64::new(0);
+
+/// A calendar date and time of day in UTC
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct DateTime {
+    /// The full year, like 2024
+    pub year: u16,
+    /// 1 to 12
+    pub month: u8,
+    /// Day of the month, from 1
+    pub day: u8,
+    /// 0 to 23
+    pub hour: u8,
+    /// 0 to 59
+    pub minute: u8,
+    /// 0 to 59
+    pub second: u8,
+}
+
+impl DateTime {
+    /// Returns the seconds since the Unix epoch
+    #[must_use]
+    pub fn unix_timestamp(&self) -> u64 {
+        let days = days_from_civil(i64::from(self.year), self.month, self.day);
+        let seconds = days * 86400
+            + i64::from(self.hour) * 3600
+            + i64::from(self.minute) * 60
+            + i64::from(self.second);
+        u64::try_from(seconds).unwrap_or(0)
+    }
+
+    /// Returns the date and time `timestamp` seconds after the Unix epoch
+    #[must_use]
+    pub fn from_unix_timestamp(timestamp: u64) -> Self {
+        let days = (timestamp / 86400) as i64;
+        let seconds = timestamp % 86400;
+        let (year, month, day) = civil_from_days(days);
+
+        Self {
+            year: year as u16,
+            month,
+            day,
+            hour: (seconds / 3600) as u8,
+            minute: (seconds % 3600 / 60) as u8,
+            second: (seconds % 60) as u8,
+        }
+    }
+}
+
+impl fmt::Display for DateTime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+            self.year, self.month, self.day, self.hour, self.minute, self.second
+        )
+    }
+}
+
+/// A point in time on the wall clock
+///
+/// It is seeded from the RTC by `init` and advanced by the timer ticks afterwards,
+/// so unlike `time::Instant` it doesn't have to be monotonic across `init` calls.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SystemTime {
+    /// Nanoseconds since the Unix epoch
+    nanos: u64,
+}
+
+impl SystemTime {
+    /// The Unix epoch, 1970-01-01 00:00:00 UTC
+    pub const UNIX_EPOCH: Self = Self { nanos: 0 };
+
+    /// Returns the current wall clock time
+    #[must_use]
+    pub fn now() -> Self {
+        let uptime = time::uptime().as_nanos() as u64;
+        Self {
+            nanos: BOOT_TIME_NANOS.load(Ordering::SeqCst) + uptime,
+        }
+    }
+
+    /// Returns the time since `earlier`, `None` if `earlier` is later than this one
+    #[must_use]
+    pub const fn duration_since(&self, earlier: Self) -> Option<Duration> {
+        match self.nanos.checked_sub(earlier.nanos) {
+            Some(nanos) => Some(Duration::from_nanos(nanos)),
+            None => None,
+        }
+    }
+
+    /// Returns the calendar date and time of this point in time
+    #[must_use]
+    pub fn date_time(&self) -> DateTime {
+        DateTime::from_unix_timestamp(self.nanos / 1_000_000_000)
+    }
+}
+
+/// Seeds the wall clock from the RTC
+///
+/// The century register is taken from the ACPI tables when `acpi::init` was called before.
+pub fn init() {
+    let now = read_date_time();
+    let uptime = time::uptime().as_nanos() as u64;
+    let nanos = now.unix_timestamp() * 1_000_000_000;
+    BOOT_TIME_NANOS.store(nanos.saturating_sub(uptime), Ordering::SeqCst);
+}
+
+/// Reads the current date and time from the RTC
+///
+/// Waits for a running update to finish and reads until two reads agree, so the
+/// values never come from the middle of an update.
+#[must_use]
+pub fn read_date_time() -> DateTime {
+    let century_register = crate::acpi::tables()
+        .and_then(|tables| tables.fadt.as_ref())
+        .map_or(0, |fadt| fadt.century_register);
+
+    let (raw, status_b) = without_interrupts(|| {
+        let mut raw = read_raw(century_register);
+        loop {
+            let again = read_raw(century_register);
+            if again == raw {
+                break;
+            }
+            raw = again;
+        }
+        (raw, read_cmos(REGISTER_STATUS_B))
+    });
+
+    let [second, minute, hour, day, month, year, century] = raw;
+    let binary = status_b & STATUS_B_BINARY != 0;
+    let decode = |value: u8| if binary { value } else { from_bcd(value) };
+
+    let pm = hour & HOUR_PM != 0;
+    let mut hour = decode(hour & !HOUR_PM);
+    if status_b & STATUS_B_24_HOUR == 0 {
+        // 12 AM is midnight and 12 PM is noon
+        hour %= 12;
+        if pm {
+            hour += 12;
+        }
+    }
+
+    let century = if century_register == 0 {
+        20
+    } else {
+        u16::from(decode(century))
+    };
+
+    DateTime {
+        year: century * 100 + u16::from(decode(year)),
+        month: decode(month),
+        day: decode(day),
+        hour,
+        minute: decode(minute),
+        second: decode(second),
+    }
+}
+
+/// Enables the periodic interrupt of the RTC on `RTC_IRQ` and returns its frequency in Hz
+///
+/// The frequency is `32768 >> (rate - 1)`, so 3 gives 8192 Hz and 15 gives 2 Hz.
+///
+/// # Errors
+///
+/// Fails if the IRQ line has no free handler slot.
+///
+/// # Panics
+///
+/// Panics if the `rate` isn't between 3 and 15.
+pub fn enable_periodic_interrupt(rate: u8) -> Result<u32, IrqError> {
+    assert!((3..=15).contains(&rate), "RTC rate {rate} is out of range");
+
+    interrupts::register_irq(RTC_IRQ, periodic_handler)?;
+    without_interrupts(|| {
+        let status_a = read_cmos(REGISTER_STATUS_A);
+        write_cmos(REGISTER_STATUS_A, (status_a & 0xF0) | rate);
+        let status_b = read_cmos(REGISTER_STATUS_B);
+        write_cmos(REGISTER_STATUS_B, status_b | STATUS_B_PERIODIC_INTERRUPT);
+        // An unread status C would keep the interrupt from ever firing
+        let _ = read_cmos(REGISTER_STATUS_C);
+    });
+
+    Ok(32768 >> (rate - 1))
+}
+
+/// Disables the periodic interrupt of the RTC
+///
+/// # Errors
+///
+/// Fails if the periodic interrupt wasn't enabled.
+pub fn disable_periodic_interrupt() -> Result<(), IrqError> {
+    without_interrupts(|| {
+        let status_b = read_cmos(REGISTER_STATUS_B);
+        write_cmos(REGISTER_STATUS_B, status_b & !STATUS_B_PERIODIC_INTERRUPT);
+        let _ = read_cmos(REGISTER_STATUS_C);
+    });
+    interrupts::unregister_irq(RTC_IRQ, periodic_handler)
+}
+
+/// Number of periodic interrupts received since boot
+#[must_use]
+pub fn periodic_interrupts() -> u64 {
+    PERIODIC_INTERRUPTS.load(Ordering::SeqCst)
+}
+
+fn periodic_handler() {
+    // The RTC doesn't raise another interrupt until status C is read
+    let _ = read_cmos(REGISTER_STATUS_C);
+    PERIODIC_INTERRUPTS.fetch_add(1, Ordering::SeqCst);
+}
+
+/// Reads the raw time registers once the RTC isn't updating them
+fn read_raw(century_register: u8) -> [u8; 7] {
+    while read_cmos(REGISTER_STATUS_A) & STATUS_A_UPDATE_IN_PROGRESS != 0 {
+        core::hint::spin_loop();
+    }
+
+    [
+        read_cmos(REGISTER_SECONDS),
+        read_cmos(REGISTER_MINUTES),
+        read_cmos(REGISTER_HOURS),
+        read_cmos(REGISTER_DAY),
+        read_cmos(REGISTER_MONTH),
+        read_cmos(REGISTER_YEAR),
+        if century_register == 0 {
+            0
+        } else {
+            read_cmos(century_register)
+        },
+    ]
+}
+
+/// Masks or unmasks the NMIs, which share the address port with the CMOS
+///
+/// They are enabled at boot. The CMOS accesses mask them only while they run.
+pub fn set_nmi_enabled(enabled: bool) {
+    let mask = if enabled { 0 } else { NMI_DISABLE };
+    without_interrupts(|| {
+        NMI_MASK.store(mask, Ordering::Relaxed);
+        unsafe { Port::new(CMOS_ADDRESS).write(mask | REGISTER_STATUS_D) };
+    });
+}
+
+/// Reads the CMOS `register`
+pub fn read_cmos(register: u8) -> u8 {
+    access_cmos(register, |data| unsafe { data.read() })
+}
+
+/// Writes `value` to the CMOS `register`
+pub fn write_cmos(register: u8, value: u8) {
+    access_cmos(register, |data| unsafe { data.write(value) });
+}
+
+/// Selects `register` with the NMIs masked and runs `access` on the data port
+///
+/// Port 0x70 can't be read back, so the NMI bit afterwards comes from `set_nmi_enabled`.
+fn access_cmos<T>(register: u8, access: impl FnOnce(&mut Port<u8>) -> T) -> T {
+    let mut address: Port<u8> = Port::new(CMOS_ADDRESS);
+    let mut data: Port<u8> = Port::new(CMOS_DATA);
+    without_interrupts(|| {
+        unsafe { address.write(NMI_DISABLE | register) };
+        let result = access(&mut data);
+        let mask = NMI_MASK.load(Ordering::Relaxed);
+        unsafe { address.write(mask | REGISTER_STATUS_D) };
+        result
+    })
+}
+
+const fn from_bcd(value: u8) -> u8 {
+    (value >> 4) * 10 + (value & 0x0F)
+}
+
+/// Days since the Unix epoch of the proleptic Gregorian date
+const fn days_from_civil(year: i64, month: u8, day: u8) -> i64 {
+    // Counting from March puts the leap day at the end of the year
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month = month as i64;
+    let day_of_year =
+        (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// The proleptic Gregorian date `days` after the Unix epoch
+const fn civil_from_days(days: i64) -> (i64, u8, u8) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * month + 2) / 5 + 1) as u8;
+    let month = (if month < 10 { month + 3 } else { month - 9 }) as u8;
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+#[test_case]
+fn test_bcd() {
+    assert_eq!(from_bcd(0x59), 59);
+    assert_eq!(from_bcd(0x00), 0);
+}
+
+#[test_case]
+fn test_unix_timestamp() {
+    let date_time = DateTime {
+        year: 2000,
+        month: 3,
+        day: 1,
+        hour: 12,
+        minute: 30,
+        second: 15,
+    };
+    assert_eq!(date_time.unix_timestamp(), 951_913_815);
+    assert_eq!(DateTime::from_unix_timestamp(951_913_815), date_time);
+    assert_eq!(DateTime::from_unix_timestamp(0).year, 1970);
+}
+
+#[test_case]
+fn test_read_date_time() {
+    let now = read_date_time();
+    assert!(now.year >= 2020);
+    assert!((1..=12).contains(&now.month));
+    assert!((1..=31).contains(&now.day));
+    assert!(now.hour < 24 && now.minute < 60 && now.second < 60);
+}
+
+#[test_case]
+fn test_periodic_interrupt() {
+    let before = periodic_interrupts();
+    assert_eq!(enable_periodic_interrupt(6), Ok(1024));
+    while periodic_interrupts() < before + 2 {
+        x86_64::instructions::hlt();
+    }
+    disable_periodic_interrupt().expect("The periodic interrupt wasn't enabled");
+}
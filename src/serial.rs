@@ -1,11 +1,22 @@
+use core::sync::atomic::{AtomicUsize, Ordering};
 use lazy_static::lazy_static;
 use spin::Mutex;
 use uart_16550::SerialPort;
 
+/// I/O port of the first serial interface
+const SERIAL1_PORT: u16 = 0x3F8;
+/// Line status register of the first serial interface
+const SERIAL1_LINE_STATUS: u16 = SERIAL1_PORT + 5;
+/// Set in the line status when a received byte is waiting
+const DATA_READY: u8 = 1 << 0;
+
+/// Prints that found `SERIAL1` locked
+static REENTRANT_PRINTS: AtomicUsize = AtomicUsize::new(0);
+
 lazy_static! {
     /// Initializes the port and sets the port 0x3F8
     pub static ref SERIAL1: Mutex<SerialPort> = {
-        let mut serial_port = unsafe { SerialPort::new(0x3F8) };
+        let mut serial_port = unsafe { SerialPort::new(SERIAL1_PORT) };
         serial_port.init();
         Mutex::new(serial_port)
     };
@@ -13,17 +24,56 @@ lazy_static! {
 
 #[doc(hidden)]
 pub fn _print(args: core::fmt::Arguments) {
+    crate::dmesg::write_fmt(args);
+    write_fmt(args);
+}
+
+/// Prints to the serial without keeping a copy in the kernel log
+pub(crate) fn write_fmt(args: core::fmt::Arguments) {
     use core::fmt::Write;
     use x86_64::instructions::interrupts;
 
     interrupts::without_interrupts(|| {
-        assert!(
-            SERIAL1.lock().write_fmt(args).is_ok(),
-            "Printing to the serial failed!"
-        );
+        // With interrupts disabled, the lock can only be held by code we interrupted
+        // on this CPU, which won't continue before we return
+        if let Some(mut serial) = SERIAL1.try_lock() {
+            let _ = serial.write_fmt(args);
+        } else {
+            REENTRANT_PRINTS.fetch_add(1, Ordering::Relaxed);
+            let mut serial = unsafe { SerialPort::new(SERIAL1_PORT) };
+            let _ = serial.write_fmt(args);
+        }
     });
 }
 
+/// Reads a received byte, `None` if there is none
+///
+/// Doesn't take `SERIAL1`, so the interrupt handler can read while a print is
+/// interrupted. Input and output use separate registers.
+pub(crate) fn try_receive() -> Option<u8> {
+    use x86_64::instructions::port::Port;
+
+    unsafe {
+        let status: u8 = Port::new(SERIAL1_LINE_STATUS).read();
+        (status & DATA_READY != 0).then(|| Port::new(SERIAL1_PORT).read())
+    }
+}
+
+/// Number of prints that interrupted another print and bypassed `SERIAL1`
+#[must_use]
+pub fn reentrant_prints() -> usize {
+    REENTRANT_PRINTS.load(Ordering::Relaxed)
+}
+
+/// Releases `SERIAL1` no matter who holds it
+///
+/// # Safety
+///
+/// The holder must never use the port again, like the code interrupted by a panic.
+pub unsafe fn force_unlock() {
+    unsafe { SERIAL1.force_unlock() };
+}
+
 /// Prints to the host through the serial interface
 #[macro_export]
 macro_rules! serial_print {
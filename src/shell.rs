@@ -0,0 +1,319 @@
+use crate::task::{keyboard::KeyStream, serial::SerialStream};
+use crate::vga_buffer::console;
+use alloc::{string::String, vec::Vec};
+use core::fmt::{self, Write};
+use futures_util::stream::StreamExt;
+use pc_keyboard::DecodedKey;
+use spin::Mutex;
+
+/// The console the shell runs on, the kernel output stays on the first one
+pub const SHELL_CONSOLE: usize = 1;
+/// Longest line in bytes the shell accepts, further input is ignored
+pub const MAX_LINE: usize = 256;
+
+/// Printed before every line
+const PROMPT: &str = "> ";
+
+/// Runs a command with the arguments after its name, printing to `out`
+pub type CommandFn = fn(out: &mut dyn Write, args: &[&str]) -> fmt::Result;
+
+/// A command the shell can run
+#[derive(Debug, Clone, Copy)]
+pub struct Command {
+    /// The word running the command
+    pub name: &'static str,
+    /// One line shown by `help`
+    pub help: &'static str,
+    pub run: CommandFn,
+}
+
+/// Errors returned by `register`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShellError {
+    /// The name is empty or contains whitespace, so it can't be typed as one word
+    InvalidName(&'static str),
+    /// There already is a command with the name
+    AlreadyRegistered(&'static str),
+}
+
+/// The commands every shell has
+const BUILTINS: [Command; 7] = [
+    Command {
+        name: "help",
+        help: "lists the commands",
+        run: help,
+    },
+    Command {
+        name: "clear",
+        help: "clears the screen",
+        run: clear,
+    },
+    Command {
+        name: "mem",
+        help: "shows the heap usage",
+        run: mem,
+    },
+    Command {
+        name: "uptime",
+        help: "shows the time since boot",
+        run: uptime,
+    },
+    Command {
+        name: "reboot",
+        help: "restarts the machine",
+        run: reboot,
+    },
+    Command {
+        name: "dmesg",
+        help: "prints the kernel log",
+        run: dmesg,
+    },
+    Command {
+        name: "echo",
+        help: "prints the arguments",
+        run: echo,
+    },
+];
+
+/// Commands added by the other modules through `register`
+static COMMANDS: Mutex<Vec<Command>> = Mutex::new(Vec::new());
+
+/// Adds `command` to the shell
+///
+/// # Errors
+///
+/// Fails if the name can't be typed as one word or is already taken.
+pub fn register(command: Command) -> Result<(), ShellError> {
+    if command.name.is_empty() || command.name.contains(char::is_whitespace) {
+        return Err(ShellError::InvalidName(command.name));
+    }
+
+    let mut commands = COMMANDS.lock();
+    if BUILTINS
+        .iter()
+        .chain(commands.iter())
+        .any(|registered| registered.name == command.name)
+    {
+        return Err(ShellError::AlreadyRegistered(command.name));
+    }
+    commands.push(command);
+    Ok(())
+}
+
+/// Returns the built-in and the registered commands
+#[must_use]
+pub fn commands() -> Vec<Command> {
+    let registered = COMMANDS.lock();
+    BUILTINS.iter().chain(registered.iter()).copied().collect()
+}
+
+/// Splits `line` into words at whitespace
+///
+/// A word starting with `"` or `'` runs to the same quote, spaces included, or to
+/// the end of the line if the quote isn't closed.
+#[must_use]
+pub fn tokenize(line: &str) -> Vec<&str> {
+    let mut words = Vec::new();
+    let mut rest = line.trim_start();
+    while let Some(first) = rest.chars().next() {
+        let (word, after) = if first == '"' || first == '\'' {
+            rest[1..].split_once(first).unwrap_or((&rest[1..], ""))
+        } else {
+            rest.split_once(char::is_whitespace).unwrap_or((rest, ""))
+        };
+        words.push(word);
+        rest = after.trim_start();
+    }
+    words
+}
+
+/// Runs the command in `line`, an empty line does nothing
+///
+/// # Errors
+///
+/// Fails if writing to `out` fails.
+pub fn execute(line: &str, out: &mut dyn Write) -> fmt::Result {
+    let words = tokenize(line);
+    let Some((&name, args)) = words.split_first() else {
+        return Ok(());
+    };
+
+    // Copied out, so the command may register commands itself
+    let command = commands().into_iter().find(|command| command.name == name);
+    match command {
+        Some(command) => (command.run)(out, args),
+        None => writeln!(out, "{name}: command not found, try `help`"),
+    }
+}
+
+/// The shell task, runs the lines typed on the keyboard or received on the serial port
+///
+/// Shows `SHELL_CONSOLE` and prints there and to the serial port, but not to the kernel log.
+pub async fn run() {
+    let keys = KeyStream::new().map(|key| match key {
+        DecodedKey::Unicode(character) => Some(character),
+        DecodedKey::RawKey(_) => None,
+    });
+    // Terminals send UTF-8, only its ASCII part is understood
+    let serial = SerialStream::new().map(|byte| byte.is_ascii().then_some(char::from(byte)));
+    let mut input = futures_util::stream::select(keys, serial);
+
+    console::switch(SHELL_CONSOLE);
+    let mut out = Output;
+    let mut line = String::new();
+    let mut after_carriage_return = false;
+    let _ = out.write_str(PROMPT);
+
+    while let Some(character) = input.next().await {
+        let Some(character) = character else {
+            continue;
+        };
+        match character {
+            // Terminals end lines with `\r` or `\r\n`, the keyboard with `\n`
+            '\n' if after_carriage_return => {}
+            '\r' | '\n' => {
+                let _ = out.write_str("\n");
+                let _ = execute(&line, &mut out);
+                line.clear();
+                let _ = out.write_str(PROMPT);
+            }
+            // Backspace from the keyboard, delete from terminals
+            '\x08' | '\x7f' => {
+                if line.pop().is_some() {
+                    let _ = out.write_str("\x08 \x08");
+                }
+            }
+            character if character.is_control() => {}
+            character if line.len() + character.len_utf8() <= MAX_LINE => {
+                line.push(character);
+                let _ = out.write_char(character);
+            }
+            _ => {}
+        }
+        after_carriage_return = character == '\r';
+    }
+}
+
+/// Prints to the shell console and the serial port
+struct Output;
+
+impl Write for Output {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        console::write_fmt(SHELL_CONSOLE, format_args!("{s}"));
+        crate::serial::write_fmt(format_args!("{s}"));
+        Ok(())
+    }
+}
+
+fn help(out: &mut dyn Write, _args: &[&str]) -> fmt::Result {
+    for command in commands() {
+        writeln!(out, "{:<12}{}", command.name, command.help)?;
+    }
+    Ok(())
+}
+
+fn clear(out: &mut dyn Write, _args: &[&str]) -> fmt::Result {
+    out.write_str("\x1b[2J\x1b[H")
+}
+
+fn mem(out: &mut dyn Write, _args: &[&str]) -> fmt::Result {
+    use crate::memory::heap::{self, HEAP_SIZE};
+
+    let used = heap::used();
+    writeln!(
+        out,
+        "heap: {} KiB used, {} KiB free, {} KiB total",
+        used / 1024,
+        HEAP_SIZE.saturating_sub(used) / 1024,
+        HEAP_SIZE / 1024
+    )
+}
+
+fn uptime(out: &mut dyn Write, _args: &[&str]) -> fmt::Result {
+    let uptime = crate::time::uptime();
+    let seconds = uptime.as_secs();
+    writeln!(
+        out,
+        "up {}:{:02}:{:02}.{:03}",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60,
+        uptime.subsec_millis()
+    )
+}
+
+fn reboot(out: &mut dyn Write, _args: &[&str]) -> fmt::Result {
+    writeln!(out, "Rebooting...")?;
+    crate::reboot()
+}
+
+fn dmesg(out: &mut dyn Write, _args: &[&str]) -> fmt::Result {
+    let mut result = Ok(());
+    crate::dmesg::dump(|text| {
+        if result.is_ok() {
+            result = out.write_str(text);
+        }
+    });
+    result
+}
+
+fn echo(out: &mut dyn Write, args: &[&str]) -> fmt::Result {
+    writeln!(out, "{}", args.join(" "))
+}
+
+#[test_case]
+fn test_tokenize() {
+    assert_eq!(
+        tokenize("  echo \"a  b\" c\t'd'  "),
+        ["echo", "a  b", "c", "d"]
+    );
+    assert_eq!(tokenize("echo 'open"), ["echo", "open"]);
+    assert!(tokenize("   ").is_empty());
+}
+
+#[test_case]
+fn test_execute_builtins() {
+    let mut out = String::new();
+    execute("echo hello   world", &mut out).expect("writing to a string failed");
+    execute("", &mut out).expect("writing to a string failed");
+    execute("nope", &mut out).expect("writing to a string failed");
+    assert_eq!(out, "hello world\nnope: command not found, try `help`\n");
+}
+
+#[test_case]
+fn test_register_command() {
+    fn greet(out: &mut dyn Write, args: &[&str]) -> fmt::Result {
+        writeln!(out, "hi {}", args.first().unwrap_or(&"there"))
+    }
+    let command = Command {
+        name: "greet",
+        help: "says hi",
+        run: greet,
+    };
+
+    assert_eq!(register(command), Ok(()));
+    assert_eq!(
+        register(command),
+        Err(ShellError::AlreadyRegistered("greet"))
+    );
+    assert_eq!(
+        register(Command {
+            name: "echo",
+            ..command
+        }),
+        Err(ShellError::AlreadyRegistered("echo"))
+    );
+    assert_eq!(
+        register(Command {
+            name: "two words",
+            ..command
+        }),
+        Err(ShellError::InvalidName("two words"))
+    );
+
+    let mut out = String::new();
+    execute("greet you", &mut out).expect("writing to a string failed");
+    execute("help", &mut out).expect("writing to a string failed");
+    assert!(out.starts_with("hi you\n"));
+    assert!(out.contains("greet       says hi\n"));
+}
@@ -0,0 +1,74 @@
+use alloc::{boxed::Box, vec::Vec};
+use core::{
+    future::Future,
+    pin::Pin,
+    sync::atomic::{AtomicU64, Ordering},
+    task::{Context, Poll},
+};
+use spin::Mutex;
+
+/// Runs the tasks and sleeps while none of them can make progress
+pub mod executor;
+/// Keyboard input delivered from the interrupt handler to async tasks
+pub mod keyboard;
+/// Serial input delivered from the interrupt handler to async tasks
+pub mod serial;
+
+/// Tasks spawned through `spawn`, waiting for the executor to pick them up
+static SPAWNED: Mutex<Vec<Task>> = Mutex::new(Vec::new());
+
+/// A unique identifier of a `Task`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct TaskId(u64);
+
+impl TaskId {
+    fn new() -> Self {
+        static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+        Self(NEXT_ID.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+/// A future running on the executor until it completes
+pub struct Task {
+    id: TaskId,
+    future: Pin<Box<dyn Future<Output = ()> + Send>>,
+}
+
+impl Task {
+    /// Wraps the `future` into a new task
+    pub fn new(future: impl Future<Output = ()> + Send + 'static) -> Self {
+        Self {
+            id: TaskId::new(),
+            future: Box::pin(future),
+        }
+    }
+
+    /// Returns the identifier of the task
+    #[must_use]
+    pub const fn id(&self) -> TaskId {
+        self.id
+    }
+
+    fn poll(&mut self, context: &mut Context) -> Poll<()> {
+        self.future.as_mut().poll(context)
+    }
+}
+
+/// Hands the `future` over to the running executor
+///
+/// This allocates, so it must not be called from interrupt handlers.
+/// Those should wake a task waiting on them instead.
+pub fn spawn(future: impl Future<Output = ()> + Send + 'static) {
+    let task = Task::new(future);
+    x86_64::instructions::interrupts::without_interrupts(|| SPAWNED.lock().push(task));
+}
+
+/// Removes all the tasks from `spawn` that weren't picked up by the executor yet
+fn take_spawned() -> Vec<Task> {
+    x86_64::instructions::interrupts::without_interrupts(|| core::mem::take(&mut *SPAWNED.lock()))
+}
+
+/// Checks for tasks from `spawn` without taking them
+fn has_spawned() -> bool {
+    x86_64::instructions::interrupts::without_interrupts(|| !SPAWNED.lock().is_empty())
+}
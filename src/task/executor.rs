@@ -0,0 +1,206 @@
+use super::{Task, TaskId};
+use alloc::{collections::BTreeMap, sync::Arc, task::Wake};
+use core::task::{Context, Poll, Waker};
+use crossbeam_queue::ArrayQueue;
+
+/// Maximum number of tasks that can be woken up at once
+const TASK_QUEUE_CAPACITY: usize = 100;
+
+/// Polls the woken tasks and halts the CPU when none of them are ready
+pub struct Executor {
+    tasks: BTreeMap<TaskId, Task>,
+    task_queue: Arc<ArrayQueue<TaskId>>,
+    waker_cache: BTreeMap<TaskId, Waker>,
+}
+
+impl Executor {
+    /// Creates an executor without any tasks
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            tasks: BTreeMap::new(),
+            task_queue: Arc::new(ArrayQueue::new(TASK_QUEUE_CAPACITY)),
+            waker_cache: BTreeMap::new(),
+        }
+    }
+
+    /// Adds the `task` and schedules it to be polled
+    ///
+    /// # Panics
+    ///
+    /// Panics if a task with the same id already exists or the task queue is full.
+    pub fn spawn(&mut self, task: Task) {
+        let task_id = task.id;
+        assert!(
+            self.tasks.insert(task_id, task).is_none(),
+            "Task with the same ID already exists"
+        );
+        self.task_queue.push(task_id).expect("Task queue is full");
+    }
+
+    /// Runs the tasks forever, halting the CPU while there is nothing to do
+    pub fn run(&mut self) -> ! {
+        loop {
+            self.run_until_idle();
+            self.sleep_if_idle();
+        }
+    }
+
+    /// Polls the tasks until none of them is ready to make progress
+    pub fn run_until_idle(&mut self) {
+        loop {
+            for task in super::take_spawned() {
+                self.spawn(task);
+            }
+            if self.task_queue.is_empty() {
+                break;
+            }
+            self.run_ready_tasks();
+        }
+    }
+
+    /// Returns `true` when all the tasks completed
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.tasks.is_empty()
+    }
+
+    fn run_ready_tasks(&mut self) {
+        // Destructure `self` to avoid borrow checker errors
+        let Self {
+            tasks,
+            task_queue,
+            waker_cache,
+        } = self;
+
+        while let Some(task_id) = task_queue.pop() {
+            let Some(task) = tasks.get_mut(&task_id) else {
+                // The task already completed
+                continue;
+            };
+
+            let waker = waker_cache
+                .entry(task_id)
+                .or_insert_with(|| TaskWaker::new_waker(task_id, task_queue.clone()));
+            let mut context = Context::from_waker(waker);
+
+            if task.poll(&mut context) == Poll::Ready(()) {
+                tasks.remove(&task_id);
+                waker_cache.remove(&task_id);
+            }
+        }
+    }
+
+    fn sleep_if_idle(&self) {
+        use x86_64::instructions::interrupts;
+
+        // An interrupt can wake a task between the check and the `hlt`,
+        // so the check has to happen with interrupts disabled
+        interrupts::disable();
+        if self.task_queue.is_empty() && !super::has_spawned() {
+            interrupts::enable_and_hlt();
+        } else {
+            interrupts::enable();
+        }
+    }
+}
+
+impl Default for Executor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Wakes a task by pushing its id to the task queue
+struct TaskWaker {
+    task_id: TaskId,
+    task_queue: Arc<ArrayQueue<TaskId>>,
+}
+
+impl TaskWaker {
+    fn new_waker(task_id: TaskId, task_queue: Arc<ArrayQueue<TaskId>>) -> Waker {
+        Waker::from(Arc::new(Self {
+            task_id,
+            task_queue,
+        }))
+    }
+
+    fn wake_task(&self) {
+        self.task_queue
+            .push(self.task_id)
+            .expect("Task queue is full");
+    }
+}
+
+impl Wake for TaskWaker {
+    fn wake(self: Arc<Self>) {
+        self.wake_task();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.wake_task();
+    }
+}
+
+// Tests
+
+#[cfg(test)]
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+#[test_case]
+fn test_spawned_tasks_complete() {
+    static COMPLETED: AtomicUsize = AtomicUsize::new(0);
+
+    let mut executor = Executor::new();
+    for _ in 0..3 {
+        executor.spawn(Task::new(async {
+            COMPLETED.fetch_add(1, Ordering::SeqCst);
+        }));
+    }
+    executor.run_until_idle();
+
+    assert_eq!(COMPLETED.load(Ordering::SeqCst), 3);
+    assert!(executor.is_empty());
+}
+
+#[test_case]
+fn test_woken_task_is_polled_again() {
+    /// Returns `Pending` once, waking itself before it does
+    struct YieldOnce(bool);
+
+    impl core::future::Future for YieldOnce {
+        type Output = ();
+
+        fn poll(mut self: core::pin::Pin<&mut Self>, context: &mut Context) -> Poll<()> {
+            if self.0 {
+                Poll::Ready(())
+            } else {
+                self.0 = true;
+                context.waker().wake_by_ref();
+                Poll::Pending
+            }
+        }
+    }
+
+    let mut executor = Executor::new();
+    executor.spawn(Task::new(YieldOnce(false)));
+    executor.run_until_idle();
+
+    assert!(executor.is_empty());
+}
+
+#[test_case]
+fn test_global_spawn() {
+    static RAN: AtomicUsize = AtomicUsize::new(0);
+
+    let mut executor = Executor::new();
+    executor.spawn(Task::new(async {
+        super::spawn(async {
+            RAN.fetch_add(1, Ordering::SeqCst);
+        });
+    }));
+    executor.run_until_idle();
+
+    assert_eq!(RAN.load(Ordering::SeqCst), 1);
+    assert!(executor.is_empty());
+}
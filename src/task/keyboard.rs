@@ -0,0 +1,311 @@
+use core::{
+    pin::Pin,
+    sync::atomic::{AtomicUsize, Ordering},
+    task::{Context, Poll},
+};
+use crossbeam_queue::ArrayQueue;
+use futures_util::{
+    stream::{Stream, StreamExt},
+    task::AtomicWaker,
+};
+use pc_keyboard::{
+    layouts, DecodedKey, HandleControl, KeyCode, KeyEvent, KeyState, Keyboard, ScancodeSet1,
+};
+
+/// Maximum number of scancodes waiting to be read
+pub const SCANCODE_QUEUE_CAPACITY: usize = 100;
+/// Lines scrolled by Shift+PageUp and Shift+PageDown
+pub const SCROLL_LINES: usize = crate::vga_buffer::BUFFER_HEIGHT / 2;
+
+/// Scancodes from the keyboard interrupt, created with the first stream
+static SCANCODE_QUEUE: spin::Once<ArrayQueue<u8>> = spin::Once::new();
+/// Wakes the task waiting for the next scancode
+static WAKER: AtomicWaker = AtomicWaker::new();
+/// Number of scancodes lost, with the ones arriving before there was a reader
+static DROPPED_SCANCODES: AtomicUsize = AtomicUsize::new(0);
+/// Number of scancodes that didn't fit into the queue, the reader didn't keep up
+static QUEUE_OVERFLOWS: AtomicUsize = AtomicUsize::new(0);
+
+/// Called by the keyboard interrupt handler
+///
+/// Must not block or allocate.
+pub(crate) fn add_scancode(scancode: u8) {
+    let Some(queue) = SCANCODE_QUEUE.r#try() else {
+        // Nobody reads the keyboard yet
+        DROPPED_SCANCODES.fetch_add(1, Ordering::Relaxed);
+        return;
+    };
+
+    if queue.push(scancode).is_err() {
+        DROPPED_SCANCODES.fetch_add(1, Ordering::Relaxed);
+        // Only report the first one, so a stuck reader doesn't flood the screen
+        if QUEUE_OVERFLOWS.fetch_add(1, Ordering::Relaxed) == 0 {
+            log::warn!("Scancode queue full; dropping keyboard input");
+        }
+        return;
+    }
+    WAKER.wake();
+}
+
+/// Number of scancodes lost because there was no reader or it didn't keep up
+#[must_use]
+pub fn dropped_scancodes() -> usize {
+    DROPPED_SCANCODES.load(Ordering::Relaxed)
+}
+
+/// Number of scancodes lost because the reader didn't keep up
+#[must_use]
+pub fn queue_overflows() -> usize {
+    QUEUE_OVERFLOWS.load(Ordering::Relaxed)
+}
+
+/// An async stream of the raw scancodes
+///
+/// There should be only one reader, as every scancode is delivered just once.
+pub struct ScancodeStream {
+    queue: &'static ArrayQueue<u8>,
+}
+
+impl ScancodeStream {
+    /// Starts collecting the scancodes, if that didn't happen yet
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            queue: SCANCODE_QUEUE.call_once(|| ArrayQueue::new(SCANCODE_QUEUE_CAPACITY)),
+        }
+    }
+}
+
+impl Default for ScancodeStream {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Stream for ScancodeStream {
+    type Item = u8;
+
+    fn poll_next(self: Pin<&mut Self>, context: &mut Context) -> Poll<Option<u8>> {
+        // Fast path, no need to register the waker
+        if let Some(scancode) = self.queue.pop() {
+            return Poll::Ready(Some(scancode));
+        }
+
+        WAKER.register(context.waker());
+        // The interrupt handler might have pushed in the meantime
+        match self.queue.pop() {
+            Some(scancode) => {
+                WAKER.take();
+                Poll::Ready(Some(scancode))
+            }
+            None => Poll::Pending,
+        }
+    }
+}
+
+/// An async stream of the decoded keys, using the US layout
+///
+/// Shift+PageUp and Shift+PageDown scroll the screen and Alt+F1 to Alt+F6 switch
+/// the virtual consoles, those keys aren't part of the stream.
+pub struct KeyStream {
+    scancodes: ScancodeStream,
+    keyboard: Keyboard<layouts::Us104Key, ScancodeSet1>,
+    left_shift: bool,
+    right_shift: bool,
+    left_alt: bool,
+    right_alt: bool,
+}
+
+impl KeyStream {
+    /// Starts decoding the scancodes
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            scancodes: ScancodeStream::new(),
+            keyboard: Keyboard::new(layouts::Us104Key, ScancodeSet1, HandleControl::Ignore),
+            left_shift: false,
+            right_shift: false,
+            left_alt: false,
+            right_alt: false,
+        }
+    }
+
+    /// Scrolls the screen or switches the console if the event belongs to one of their keys
+    ///
+    /// Returns whether the event was consumed.
+    fn handle_screen_keys(&mut self, event: &KeyEvent) -> bool {
+        let pressed = event.state == KeyState::Down;
+        match event.code {
+            // The keyboard tracks them as well, so they are never consumed
+            KeyCode::ShiftLeft => self.left_shift = pressed,
+            KeyCode::ShiftRight => self.right_shift = pressed,
+            KeyCode::AltLeft => self.left_alt = pressed,
+            KeyCode::AltRight => self.right_alt = pressed,
+            code if self.left_alt || self.right_alt => {
+                let console = match code {
+                    KeyCode::F1 => 0,
+                    KeyCode::F2 => 1,
+                    KeyCode::F3 => 2,
+                    KeyCode::F4 => 3,
+                    KeyCode::F5 => 4,
+                    KeyCode::F6 => 5,
+                    _ => return false,
+                };
+                if pressed {
+                    crate::vga_buffer::console::switch(console);
+                }
+                return true;
+            }
+            KeyCode::PageUp | KeyCode::PageDown if self.left_shift || self.right_shift => {
+                if pressed && event.code == KeyCode::PageUp {
+                    crate::vga_buffer::scroll_up(SCROLL_LINES);
+                } else if pressed {
+                    crate::vga_buffer::scroll_down(SCROLL_LINES);
+                }
+                return true;
+            }
+            _ => {}
+        }
+        false
+    }
+}
+
+impl Default for KeyStream {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Stream for KeyStream {
+    type Item = DecodedKey;
+
+    fn poll_next(mut self: Pin<&mut Self>, context: &mut Context) -> Poll<Option<DecodedKey>> {
+        // A key press can take several scancodes, so we keep going until one decodes
+        while let Some(scancode) = futures_util::ready!(self.scancodes.poll_next_unpin(context)) {
+            if let Ok(Some(key_event)) = self.keyboard.add_byte(scancode) {
+                if self.handle_screen_keys(&key_event) {
+                    continue;
+                }
+                if let Some(key) = self.keyboard.process_keyevent(key_event) {
+                    return Poll::Ready(Some(key));
+                }
+            }
+        }
+
+        Poll::Ready(None)
+    }
+}
+
+/// A task echoing the pressed keys to the screen
+pub async fn print_keypresses() {
+    use crate::print;
+
+    let mut keys = KeyStream::new();
+    while let Some(key) = keys.next().await {
+        match key {
+            // Backspace only moves the cursor, the space erases the character
+            DecodedKey::Unicode('\x08') => print!("\x08 \x08"),
+            DecodedKey::Unicode(character) => print!("{character}"),
+            DecodedKey::RawKey(key) => print!("{key:?}"),
+        }
+    }
+}
+
+// Tests
+
+#[test_case]
+fn test_scancodes_are_queued_in_order() {
+    let mut stream = ScancodeStream::new();
+    let mut context = Context::from_waker(core::task::Waker::noop());
+
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        add_scancode(0x1E);
+        add_scancode(0x9E);
+    });
+
+    assert_eq!(
+        stream.poll_next_unpin(&mut context),
+        Poll::Ready(Some(0x1E))
+    );
+    assert_eq!(
+        stream.poll_next_unpin(&mut context),
+        Poll::Ready(Some(0x9E))
+    );
+    assert_eq!(stream.poll_next_unpin(&mut context), Poll::Pending);
+}
+
+#[test_case]
+fn test_overflow_is_counted() {
+    let mut stream = ScancodeStream::new();
+    let mut context = Context::from_waker(core::task::Waker::noop());
+    let (dropped, overflows) = (dropped_scancodes(), queue_overflows());
+
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        for _ in 0..=SCANCODE_QUEUE_CAPACITY {
+            add_scancode(0x1E);
+        }
+    });
+    assert_eq!(dropped_scancodes(), dropped + 1);
+    assert_eq!(queue_overflows(), overflows + 1);
+
+    while stream.poll_next_unpin(&mut context).is_ready() {}
+}
+
+#[test_case]
+fn test_keys_are_decoded() {
+    let mut keys = KeyStream::new();
+    let mut context = Context::from_waker(core::task::Waker::noop());
+
+    // Press and release of `A`
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        add_scancode(0x1E);
+        add_scancode(0x9E);
+    });
+
+    assert_eq!(
+        keys.poll_next_unpin(&mut context),
+        Poll::Ready(Some(DecodedKey::Unicode('a')))
+    );
+    assert_eq!(keys.poll_next_unpin(&mut context), Poll::Pending);
+}
+
+#[test_case]
+fn test_shift_page_up_is_consumed() {
+    let mut keys = KeyStream::new();
+    let mut context = Context::from_waker(core::task::Waker::noop());
+
+    // Left shift down, PageUp press and release (extended), left shift up, then `A`
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        for scancode in [0x2A, 0xE0, 0x49, 0xE0, 0xC9, 0xAA, 0x1E, 0x9E] {
+            add_scancode(scancode);
+        }
+    });
+
+    assert_eq!(
+        keys.poll_next_unpin(&mut context),
+        Poll::Ready(Some(DecodedKey::Unicode('a')))
+    );
+    crate::vga_buffer::scroll_down(usize::MAX);
+}
+
+#[test_case]
+fn test_alt_f2_switches_the_console() {
+    use crate::vga_buffer::console;
+
+    let mut keys = KeyStream::new();
+    let mut context = Context::from_waker(core::task::Waker::noop());
+
+    // Left alt down, F2 press and release, left alt up, then `A`
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        for scancode in [0x38, 0x3C, 0xBC, 0xB8, 0x1E, 0x9E] {
+            add_scancode(scancode);
+        }
+    });
+
+    assert_eq!(
+        keys.poll_next_unpin(&mut context),
+        Poll::Ready(Some(DecodedKey::Unicode('a')))
+    );
+    assert_eq!(console::active(), 1);
+    console::switch(console::KERNEL_CONSOLE);
+}
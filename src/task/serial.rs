@@ -0,0 +1,106 @@
+use core::{
+    pin::Pin,
+    sync::atomic::{AtomicUsize, Ordering},
+    task::{Context, Poll},
+};
+use crossbeam_queue::ArrayQueue;
+use futures_util::{stream::Stream, task::AtomicWaker};
+
+/// Maximum number of received bytes waiting to be read
+pub const INPUT_QUEUE_CAPACITY: usize = 256;
+
+/// Bytes from the serial interrupt, created with the first stream
+static INPUT_QUEUE: spin::Once<ArrayQueue<u8>> = spin::Once::new();
+/// Wakes the task waiting for the next byte
+static WAKER: AtomicWaker = AtomicWaker::new();
+/// Number of bytes that didn't fit into the queue
+static DROPPED_BYTES: AtomicUsize = AtomicUsize::new(0);
+
+/// Called by the serial interrupt handler
+///
+/// Must not block or allocate.
+pub(crate) fn add_byte(byte: u8) {
+    let Some(queue) = INPUT_QUEUE.r#try() else {
+        // Nobody reads the serial port yet
+        DROPPED_BYTES.fetch_add(1, Ordering::Relaxed);
+        return;
+    };
+
+    if queue.push(byte).is_err() {
+        DROPPED_BYTES.fetch_add(1, Ordering::Relaxed);
+        return;
+    }
+    WAKER.wake();
+}
+
+/// Number of received bytes lost because there was no reader or it didn't keep up
+#[must_use]
+pub fn dropped_bytes() -> usize {
+    DROPPED_BYTES.load(Ordering::Relaxed)
+}
+
+/// An async stream of the bytes received on the first serial port
+///
+/// There should be only one reader, as every byte is delivered just once.
+pub struct SerialStream {
+    queue: &'static ArrayQueue<u8>,
+}
+
+impl SerialStream {
+    /// Starts collecting the received bytes, if that didn't happen yet
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            queue: INPUT_QUEUE.call_once(|| ArrayQueue::new(INPUT_QUEUE_CAPACITY)),
+        }
+    }
+}
+
+impl Default for SerialStream {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Stream for SerialStream {
+    type Item = u8;
+
+    fn poll_next(self: Pin<&mut Self>, context: &mut Context) -> Poll<Option<u8>> {
+        if let Some(byte) = self.queue.pop() {
+            return Poll::Ready(Some(byte));
+        }
+
+        WAKER.register(context.waker());
+        // The interrupt handler might have pushed in the meantime
+        match self.queue.pop() {
+            Some(byte) => {
+                WAKER.take();
+                Poll::Ready(Some(byte))
+            }
+            None => Poll::Pending,
+        }
+    }
+}
+
+#[test_case]
+fn test_bytes_are_queued_in_order() {
+    use futures_util::StreamExt;
+
+    let mut stream = SerialStream::new();
+    let mut context = Context::from_waker(core::task::Waker::noop());
+
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        add_byte(b'l');
+        add_byte(b's');
+    });
+
+    assert_eq!(
+        stream.poll_next_unpin(&mut context),
+        Poll::Ready(Some(b'l'))
+    );
+    assert_eq!(
+        stream.poll_next_unpin(&mut context),
+        Poll::Ready(Some(b's'))
+    );
+    assert_eq!(stream.poll_next_unpin(&mut context), Poll::Pending);
+}
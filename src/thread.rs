@@ -0,0 +1,469 @@
+use crate::interrupts::{self, TIMER_IRQ};
+use crate::time::{Duration, Instant};
+use alloc::{
+    boxed::Box,
+    collections::{BTreeMap, VecDeque},
+    sync::Arc,
+    vec,
+    vec::Vec,
+};
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use spin::Mutex;
+use x86_64::instructions::interrupts::without_interrupts;
+
+/// Size of the stack of every spawned thread in bytes
+pub const STACK_SIZE: usize = 16 * 1024;
+/// How long a thread runs before it is preempted
+pub const TIME_SLICE: Duration = Duration::from_millis(10);
+
+/// Initial `RFLAGS` of a new thread, only the reserved bit is set so interrupts stay disabled
+const INITIAL_RFLAGS: u64 = 0x2;
+/// Fills the lowest bytes of every spawned stack, a thread that overwrote it overflowed
+///
+/// The stacks are on the heap without a guard page, so this is checked on every switch.
+const STACK_CANARY: [u8; 64] = [0xA5; 64];
+
+/// The scheduler, `None` until `init` is called
+///
+/// It must only be locked with interrupts disabled, otherwise the timer could preempt
+/// the thread holding it.
+static SCHEDULER: Mutex<Option<Scheduler>> = Mutex::new(None);
+/// Set by the timer when the running thread used up its time slice
+static NEED_RESCHEDULE: AtomicBool = AtomicBool::new(false);
+
+core::arch::global_asm!(
+    ".global rudos_switch_context",
+    "rudos_switch_context:",
+    // Save the callee saved registers of the current thread on its own stack
+    "push rbp",
+    "push rbx",
+    "push r12",
+    "push r13",
+    "push r14",
+    "push r15",
+    "pushfq",
+    "mov [rdi], rsp",
+    // Continue on the stack of the next thread, where the same registers wait
+    "mov rsp, rsi",
+    "popfq",
+    "pop r15",
+    "pop r14",
+    "pop r13",
+    "pop r12",
+    "pop rbx",
+    "pop rbp",
+    "ret",
+);
+
+extern "C" {
+    /// Saves the current register context to `old_rsp` and resumes the one at `new_rsp`
+    ///
+    /// Returns once another switch resumes the context saved to `old_rsp`.
+    fn rudos_switch_context(old_rsp: *mut u64, new_rsp: u64);
+}
+
+/// A unique identifier of a kernel thread
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ThreadId(u64);
+
+impl ThreadId {
+    fn new() -> Self {
+        static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+        Self(NEXT_ID.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Running,
+    Ready,
+    /// Waits until the instant
+    Sleeping(Instant),
+    /// Waits for another thread to finish
+    Blocked,
+    /// Done, the stack is freed as soon as another thread runs
+    Finished,
+}
+
+struct Thread {
+    state: State,
+    /// Stack pointer of the saved register context while the thread isn't running
+    rsp: u64,
+    /// `None` for the boot thread, which keeps the stack the bootloader gave it
+    stack: Option<Box<[u8]>>,
+    /// Taken by the thread when it starts running
+    entry: Option<Box<dyn FnOnce() + Send>>,
+    /// Threads blocked in `JoinHandle::join` on this one
+    joiners: Vec<ThreadId>,
+}
+
+impl Thread {
+    /// Creates a thread whose saved context returns into `thread_start`
+    fn new(entry: Box<dyn FnOnce() + Send>) -> Self {
+        let mut stack = vec![0u8; STACK_SIZE].into_boxed_slice();
+        stack[..STACK_CANARY.len()].copy_from_slice(&STACK_CANARY);
+        let stack_top = (stack.as_mut_ptr() as u64 + STACK_SIZE as u64) & !0xF;
+
+        // The same layout `rudos_switch_context` leaves behind, the zero after the return
+        // address stands in for the return address of `thread_start` to keep the ABI alignment
+        let context = [
+            INITIAL_RFLAGS,
+            0, // r15
+            0, // r14
+            0, // r13
+            0, // r12
+            0, // rbx
+            0, // rbp
+            thread_start as *const () as u64,
+            0,
+        ];
+        let rsp = stack_top - (context.len() * 8) as u64;
+        unsafe { core::ptr::write(rsp as *mut [u64; 9], context) };
+
+        Self {
+            state: State::Ready,
+            rsp,
+            stack: Some(stack),
+            entry: Some(entry),
+            joiners: Vec::new(),
+        }
+    }
+
+    /// Whether the canary at the end of the stack is untouched
+    fn stack_intact(&self) -> bool {
+        self.stack
+            .as_ref()
+            .is_none_or(|stack| stack.starts_with(&STACK_CANARY))
+    }
+}
+
+struct Scheduler {
+    /// Boxed, so the saved stack pointers don't move while a switch writes them
+    threads: BTreeMap<ThreadId, Box<Thread>>,
+    ready: VecDeque<ThreadId>,
+    current: ThreadId,
+    /// When the current thread got the CPU
+    slice_start: Instant,
+    /// Runs whenever no other thread is ready, it is never queued
+    idle: ThreadId,
+}
+
+impl Scheduler {
+    fn current_mut(&mut self) -> &mut Thread {
+        self.threads
+            .get_mut(&self.current)
+            .expect("The current thread doesn't exist")
+    }
+
+    fn add(&mut self, thread: Thread) -> ThreadId {
+        let id = ThreadId::new();
+        self.threads.insert(id, Box::new(thread));
+        self.ready.push_back(id);
+        id
+    }
+
+    fn make_ready(&mut self, id: ThreadId) {
+        if let Some(thread) = self.threads.get_mut(&id) {
+            thread.state = State::Ready;
+            self.ready.push_back(id);
+        }
+    }
+
+    /// Frees the finished threads, except the current one which still runs on its stack
+    fn reap(&mut self) {
+        let current = self.current;
+        self.threads
+            .retain(|&id, thread| id == current || thread.state != State::Finished);
+    }
+
+    /// Picks the next thread and returns where to save the current context and what to resume
+    ///
+    /// Returns `None` when the current thread keeps running.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the current thread overflowed its stack.
+    fn switch(&mut self) -> Option<(*mut u64, u64)> {
+        let current = self.current;
+        let idle = self.idle;
+
+        let current_thread = self.current_mut();
+        assert!(
+            current_thread.stack_intact(),
+            "Thread {current:?} overflowed its stack"
+        );
+        let still_runnable = current_thread.state == State::Running;
+        if still_runnable && current != idle {
+            current_thread.state = State::Ready;
+            self.ready.push_back(current);
+        }
+
+        let next = match self.ready.pop_front() {
+            Some(next) => next,
+            None if still_runnable => current,
+            None => idle,
+        };
+
+        let next_thread = self
+            .threads
+            .get_mut(&next)
+            .expect("Queued thread doesn't exist");
+        next_thread.state = State::Running;
+        self.slice_start = Instant::now();
+        if next == current {
+            return None;
+        }
+        let new_rsp = next_thread.rsp;
+        let old_rsp: *mut u64 = &mut self.current_mut().rsp;
+        self.current = next;
+
+        Some((old_rsp, new_rsp))
+    }
+}
+
+/// Turns the running code into the boot thread and starts preempting it on timer ticks
+///
+/// The heap has to be initialized first, the thread stacks live on it.
+///
+/// # Panics
+///
+/// Panics if the timer handler could not be registered.
+pub fn init() {
+    let initialized = without_interrupts(|| {
+        let mut scheduler = SCHEDULER.lock();
+        if scheduler.is_some() {
+            return false;
+        }
+
+        let boot = ThreadId::new();
+        let mut threads = BTreeMap::new();
+        threads.insert(
+            boot,
+            Box::new(Thread {
+                state: State::Running,
+                rsp: 0,
+                stack: None,
+                entry: None,
+                joiners: Vec::new(),
+            }),
+        );
+
+        let idle = ThreadId::new();
+        threads.insert(
+            idle,
+            Box::new(Thread::new(Box::new(|| loop {
+                x86_64::instructions::hlt();
+            }))),
+        );
+
+        *scheduler = Some(Scheduler {
+            threads,
+            ready: VecDeque::new(),
+            current: boot,
+            slice_start: Instant::now(),
+            idle,
+        });
+        true
+    });
+
+    if initialized {
+        interrupts::register_irq(TIMER_IRQ, tick).expect("Registering the scheduler tick failed");
+    }
+}
+
+/// A handle to wait for a spawned thread and take its result
+pub struct JoinHandle<T> {
+    id: ThreadId,
+    result: Arc<Mutex<Option<T>>>,
+}
+
+impl<T> JoinHandle<T> {
+    /// Returns the identifier of the thread
+    #[must_use]
+    pub const fn id(&self) -> ThreadId {
+        self.id
+    }
+
+    /// Blocks the current thread until the thread finishes and returns its result
+    ///
+    /// # Panics
+    ///
+    /// Panics if the scheduler isn't initialized.
+    pub fn join(self) -> T {
+        without_interrupts(|| {
+            let mut guard = SCHEDULER.lock();
+            let scheduler = guard.as_mut().expect("The scheduler isn't initialized");
+            let current = scheduler.current;
+
+            let Some(thread) = scheduler.threads.get_mut(&self.id) else {
+                // Already finished and freed
+                return;
+            };
+            if thread.state == State::Finished {
+                return;
+            }
+            thread.joiners.push(current);
+            scheduler.current_mut().state = State::Blocked;
+
+            drop(guard);
+            schedule();
+        });
+
+        self.result
+            .lock()
+            .take()
+            .expect("A finished thread always leaves its result")
+    }
+}
+
+/// Starts running `f` in a new kernel thread
+///
+/// # Panics
+///
+/// Panics if the scheduler isn't initialized.
+pub fn spawn<F, T>(f: F) -> JoinHandle<T>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    let result = Arc::new(Mutex::new(None));
+    let thread_result = Arc::clone(&result);
+    let thread = Thread::new(Box::new(move || {
+        let value = f();
+        *thread_result.lock() = Some(value);
+    }));
+
+    let id = without_interrupts(|| {
+        SCHEDULER
+            .lock()
+            .as_mut()
+            .expect("The scheduler isn't initialized")
+            .add(thread)
+    });
+
+    JoinHandle { id, result }
+}
+
+/// Returns the identifier of the running thread, `None` before `init`
+#[must_use]
+pub fn current() -> Option<ThreadId> {
+    without_interrupts(|| SCHEDULER.lock().as_ref().map(|scheduler| scheduler.current))
+}
+
+/// Lets the other ready threads run before the current one continues
+pub fn yield_now() {
+    without_interrupts(schedule);
+}
+
+/// Puts the current thread to sleep for at least `duration`
+///
+/// # Panics
+///
+/// Panics if the scheduler isn't initialized.
+pub fn sleep(duration: Duration) {
+    without_interrupts(|| {
+        let wake_at = Instant::now() + duration;
+        let mut guard = SCHEDULER.lock();
+        let scheduler = guard.as_mut().expect("The scheduler isn't initialized");
+        scheduler.current_mut().state = State::Sleeping(wake_at);
+
+        drop(guard);
+        schedule();
+    });
+}
+
+/// Ends the current thread and wakes the threads joining it
+fn exit() -> ! {
+    x86_64::instructions::interrupts::disable();
+    {
+        let mut guard = SCHEDULER.lock();
+        let scheduler = guard.as_mut().expect("The scheduler isn't initialized");
+        let thread = scheduler.current_mut();
+        thread.state = State::Finished;
+        let joiners = core::mem::take(&mut thread.joiners);
+        for joiner in joiners {
+            scheduler.make_ready(joiner);
+        }
+    }
+    schedule();
+
+    unreachable!("A finished thread was scheduled again");
+}
+
+/// Where every spawned thread starts, right after its first context switch
+extern "C" fn thread_start() -> ! {
+    let entry = SCHEDULER
+        .lock()
+        .as_mut()
+        .and_then(|scheduler| scheduler.current_mut().entry.take());
+
+    // The switch happened with interrupts disabled, for the new thread they have to be enabled
+    x86_64::instructions::interrupts::enable();
+    if let Some(entry) = entry {
+        entry();
+    }
+    exit();
+}
+
+/// Switches to the next ready thread, must be called with interrupts disabled
+///
+/// The current thread has to be `Running` to be queued again, otherwise it only
+/// comes back once something makes it ready.
+fn schedule() {
+    let switch = {
+        let mut guard = SCHEDULER.lock();
+        let Some(scheduler) = guard.as_mut() else {
+            return;
+        };
+        scheduler.reap();
+        scheduler.switch()
+    };
+
+    if let Some((old_rsp, new_rsp)) = switch {
+        // The lock is released, the next thread may need it right away
+        unsafe { rudos_switch_context(old_rsp, new_rsp) };
+    }
+}
+
+/// Wakes the sleeping threads and asks for a switch when the time slice is used up
+fn tick() {
+    let now = Instant::now();
+
+    if let Some(scheduler) = SCHEDULER.lock().as_mut() {
+        let woken: Vec<ThreadId> = scheduler
+            .threads
+            .iter()
+            .filter(|(_, thread)| matches!(thread.state, State::Sleeping(until) if until <= now))
+            .map(|(&id, _)| id)
+            .collect();
+        for id in woken {
+            scheduler.make_ready(id);
+        }
+
+        if now - scheduler.slice_start >= TIME_SLICE {
+            NEED_RESCHEDULE.store(true, Ordering::SeqCst);
+        }
+    }
+}
+
+/// Switches threads if the timer asked for it, called by the IRQ dispatch after the EOI
+///
+/// The preempted thread continues from here and returns from its interrupt once it
+/// is scheduled again.
+pub(crate) fn preempt_if_requested() {
+    if NEED_RESCHEDULE.swap(false, Ordering::SeqCst) {
+        schedule();
+    }
+}
+
+#[test_case]
+fn test_stack_canary() {
+    let mut thread = Thread::new(Box::new(|| {}));
+    assert!(thread.stack_intact());
+
+    // Like a thread writing past the end of its stack
+    if let Some(stack) = &mut thread.stack {
+        stack[0] = 0;
+    }
+    assert!(!thread.stack_intact());
+}
@@ -0,0 +1,400 @@
+use core::ops::{Add, AddAssign, Sub};
+use core::sync::atomic::{AtomicU32, AtomicU64, AtomicU8, Ordering};
+use spin::Mutex;
+use x86_64::instructions::{interrupts::without_interrupts, port::Port};
+use x86_64::structures::paging::{FrameAllocator, Mapper, Size4KiB};
+
+pub use core::time::Duration;
+
+/// The high precision event timer found through ACPI
+pub mod hpet;
+/// The time stamp counter of the CPU
+pub mod tsc;
+/// Timeouts and async sleeps on a hierarchical timer wheel
+pub mod wheel;
+
+pub use wheel::{set_timeout, TimeoutHandle};
+
+/// Frequency of the oscillator driving the PIT in Hz
+pub const PIT_BASE_FREQUENCY: u32 = 1_193_182;
+/// Timer frequency set up by `crate::init`
+pub const DEFAULT_FREQUENCY: u32 = 1000;
+
+/// Data port of PIT channel 0, which is wired to IRQ 0
+const PIT_CHANNEL_0: u16 = 0x40;
+/// Mode/command port of the PIT
+const PIT_COMMAND: u16 = 0x43;
+/// Channel 0, low byte then high byte, mode 3 (square wave), binary counting
+const PIT_CHANNEL_0_SQUARE_WAVE: u8 = 0b0011_0110;
+
+/// Frequency channel 0 currently runs at, the BIOS leaves it at about 18.2 Hz
+static FREQUENCY: AtomicU32 = AtomicU32::new(PIT_BASE_FREQUENCY / 65536);
+/// Nanoseconds between two ticks at the current frequency
+static TICK_NANOS: AtomicU64 = AtomicU64::new(1_000_000_000 * 65536 / PIT_BASE_FREQUENCY as u64);
+/// Timer interrupts since boot
+static TICKS: AtomicU64 = AtomicU64::new(0);
+/// Nanoseconds since boot, advanced by every tick
+static UPTIME_NANOS: AtomicU64 = AtomicU64::new(0);
+
+/// The `ClockSource` read by `Instant::now`
+static CLOCK_SOURCE: AtomicU8 = AtomicU8::new(ClockSource::Pit as u8);
+/// Counter of the clock source when it took over
+static ANCHOR_COUNTER: AtomicU64 = AtomicU64::new(0);
+/// Nanoseconds since boot when the clock source took over
+static ANCHOR_NANOS: AtomicU64 = AtomicU64::new(0);
+
+/// Maximum number of pending one-shot deadlines
+pub const MAX_DEADLINES: usize = 16;
+
+/// Pending one-shot deadlines, checked on every tick
+static DEADLINES: Mutex<[Option<Deadline>; MAX_DEADLINES]> = Mutex::new([None; MAX_DEADLINES]);
+
+/// The hardware `Instant::now` reads
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum ClockSource {
+    /// The PIT tick counter, the resolution is one tick
+    Pit,
+    /// The main counter of the HPET
+    Hpet,
+    /// The calibrated time stamp counter
+    Tsc,
+}
+
+/// A function called from the timer interrupt once its deadline passed
+///
+/// It runs with interrupts disabled, just like an `IrqHandler`.
+pub type DeadlineCallback = fn();
+
+/// Identifies a pending deadline so it can be cancelled
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DeadlineId(u64);
+
+/// Errors returned by `set_deadline`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimerError {
+    /// There are already `MAX_DEADLINES` pending deadlines
+    TooManyDeadlines,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Deadline {
+    id: DeadlineId,
+    at: Instant,
+    callback: DeadlineCallback,
+}
+
+/// Programs PIT channel 0 to fire at `frequency` Hz
+pub fn init(frequency: u32) {
+    set_frequency(frequency);
+}
+
+/// Switches `Instant::now` to the best clock the machine has and returns it
+///
+/// The HPET needs `acpi::init` to be called first. The TSC is calibrated against the
+/// HPET or the PIT and used when it runs at a constant rate or there is no HPET.
+pub fn init_high_resolution(
+    mapper: &mut impl Mapper<Size4KiB>,
+    frame_allocator: &mut impl FrameAllocator<Size4KiB>,
+) -> ClockSource {
+    // Without an HPET the ticks are still good enough to calibrate the TSC
+    let _ = hpet::init(mapper, frame_allocator);
+    tsc::calibrate();
+
+    let source = if tsc::is_invariant() || !hpet::is_available() {
+        ClockSource::Tsc
+    } else {
+        ClockSource::Hpet
+    };
+
+    without_interrupts(|| {
+        ANCHOR_NANOS.store(nanos_since_boot(), Ordering::SeqCst);
+        ANCHOR_COUNTER.store(
+            match source {
+                ClockSource::Pit => 0,
+                ClockSource::Hpet => hpet::counter().unwrap_or_default(),
+                ClockSource::Tsc => tsc::read(),
+            },
+            Ordering::SeqCst,
+        );
+        CLOCK_SOURCE.store(source as u8, Ordering::SeqCst);
+    });
+
+    source
+}
+
+/// Returns the hardware `Instant::now` reads
+#[must_use]
+pub fn clock_source() -> ClockSource {
+    match CLOCK_SOURCE.load(Ordering::SeqCst) {
+        1 => ClockSource::Hpet,
+        2 => ClockSource::Tsc,
+        _ => ClockSource::Pit,
+    }
+}
+
+/// Changes the frequency of the timer interrupt and returns the one actually set
+///
+/// The PIT can only divide its base frequency by 1 to 65536, the result is the closest
+/// frequency it can do.
+pub fn set_frequency(frequency: u32) -> u32 {
+    let divisor = (PIT_BASE_FREQUENCY + frequency.max(1) / 2) / frequency.max(1);
+    let divisor = divisor.clamp(1, 65536);
+
+    without_interrupts(|| {
+        let mut command: Port<u8> = Port::new(PIT_COMMAND);
+        let mut data: Port<u8> = Port::new(PIT_CHANNEL_0);
+        unsafe {
+            command.write(PIT_CHANNEL_0_SQUARE_WAVE);
+            // A divisor of 65536 is written as zero
+            data.write(divisor as u8);
+            data.write((divisor >> 8) as u8);
+        }
+
+        FREQUENCY.store(PIT_BASE_FREQUENCY / divisor, Ordering::SeqCst);
+        TICK_NANOS.store(
+            1_000_000_000 * u64::from(divisor) / u64::from(PIT_BASE_FREQUENCY),
+            Ordering::SeqCst,
+        );
+    });
+
+    frequency_hz()
+}
+
+/// Returns the frequency of the timer interrupt in Hz
+#[must_use]
+pub fn frequency_hz() -> u32 {
+    FREQUENCY.load(Ordering::SeqCst)
+}
+
+/// Returns the number of timer interrupts since boot
+#[must_use]
+pub fn ticks() -> u64 {
+    TICKS.load(Ordering::SeqCst)
+}
+
+/// Returns the time since boot
+#[must_use]
+pub fn uptime() -> Duration {
+    Duration::from_nanos(nanos_since_boot())
+}
+
+/// Waits at least `duration` without spinning
+///
+/// Once the scheduler runs only the current thread sleeps, before that the CPU halts
+/// between the timer interrupts.
+pub fn sleep(duration: Duration) {
+    if crate::thread::current().is_some() {
+        crate::thread::sleep(duration);
+        return;
+    }
+
+    let deadline = Instant::now() + duration;
+    while Instant::now() < deadline {
+        x86_64::instructions::hlt();
+    }
+}
+
+/// Calls `callback` from the timer interrupt once `at` has passed
+///
+/// The deadline is compared against `Instant::now` on every tick, so the callback
+/// runs up to one tick late.
+///
+/// # Errors
+///
+/// Fails if there are already `MAX_DEADLINES` pending deadlines.
+pub fn set_deadline(at: Instant, callback: DeadlineCallback) -> Result<DeadlineId, TimerError> {
+    static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+    without_interrupts(|| {
+        let mut deadlines = DEADLINES.lock();
+        let slot = deadlines
+            .iter_mut()
+            .find(|slot| slot.is_none())
+            .ok_or(TimerError::TooManyDeadlines)?;
+        let id = DeadlineId(NEXT_ID.fetch_add(1, Ordering::Relaxed));
+        *slot = Some(Deadline { id, at, callback });
+        Ok(id)
+    })
+}
+
+/// Removes a pending deadline, returns `false` if it already fired or was cancelled
+pub fn cancel_deadline(id: DeadlineId) -> bool {
+    without_interrupts(|| {
+        let mut deadlines = DEADLINES.lock();
+        let slot = deadlines
+            .iter_mut()
+            .find(|slot| slot.is_some_and(|deadline| deadline.id == id));
+        slot.map(|slot| slot.take()).is_some()
+    })
+}
+
+/// Counts a timer interrupt and fires the passed deadlines, called by the timer handler
+pub(crate) fn tick() {
+    TICKS.fetch_add(1, Ordering::SeqCst);
+    UPTIME_NANOS.fetch_add(TICK_NANOS.load(Ordering::SeqCst), Ordering::SeqCst);
+
+    // Take them out first, so the callbacks can set new deadlines
+    let now = Instant::now();
+    let mut due = [None; MAX_DEADLINES];
+    for (slot, due) in DEADLINES.lock().iter_mut().zip(&mut due) {
+        if slot.is_some_and(|deadline| deadline.at <= now) {
+            *due = slot.take();
+        }
+    }
+    for deadline in due.into_iter().flatten() {
+        (deadline.callback)();
+    }
+
+    wheel::advance();
+}
+
+/// Nanoseconds since boot read from the current `ClockSource`
+fn nanos_since_boot() -> u64 {
+    let anchor_nanos = ANCHOR_NANOS.load(Ordering::SeqCst);
+    let anchor_counter = ANCHOR_COUNTER.load(Ordering::SeqCst);
+
+    match clock_source() {
+        ClockSource::Pit => UPTIME_NANOS.load(Ordering::SeqCst),
+        ClockSource::Hpet => {
+            let counter = hpet::counter().unwrap_or(anchor_counter);
+            anchor_nanos + hpet::counter_to_nanos(counter.wrapping_sub(anchor_counter))
+        }
+        ClockSource::Tsc => {
+            let frequency = tsc::frequency_hz().unwrap_or(1);
+            let elapsed = u128::from(tsc::read().wrapping_sub(anchor_counter));
+            anchor_nanos + (elapsed * 1_000_000_000 / u128::from(frequency)) as u64
+        }
+    }
+}
+
+/// A point in time on the monotonic clock, which starts at boot
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Instant {
+    /// Nanoseconds since boot
+    nanos: u64,
+}
+
+impl Instant {
+    /// Returns the current time
+    #[must_use]
+    pub fn now() -> Self {
+        Self {
+            nanos: nanos_since_boot(),
+        }
+    }
+
+    /// Nanoseconds since boot
+    const fn as_nanos(&self) -> u64 {
+        self.nanos
+    }
+
+    /// Returns the time passed since `earlier`, zero if `earlier` is later than this one
+    #[must_use]
+    pub const fn duration_since(&self, earlier: Self) -> Duration {
+        Duration::from_nanos(self.nanos.saturating_sub(earlier.nanos))
+    }
+
+    /// Returns the time passed since this instant
+    #[must_use]
+    pub fn elapsed(&self) -> Duration {
+        Self::now().duration_since(*self)
+    }
+
+    /// Returns the instant `duration` later, `None` if it doesn't fit
+    #[must_use]
+    pub fn checked_add(&self, duration: Duration) -> Option<Self> {
+        let nanos = u64::try_from(duration.as_nanos()).ok()?;
+        Some(Self {
+            nanos: self.nanos.checked_add(nanos)?,
+        })
+    }
+}
+
+impl Add<Duration> for Instant {
+    type Output = Self;
+
+    fn add(self, duration: Duration) -> Self {
+        self.checked_add(duration)
+            .expect("Overflow when adding a duration to an instant")
+    }
+}
+
+impl AddAssign<Duration> for Instant {
+    fn add_assign(&mut self, duration: Duration) {
+        *self = *self + duration;
+    }
+}
+
+impl Sub for Instant {
+    type Output = Duration;
+
+    fn sub(self, earlier: Self) -> Duration {
+        self.duration_since(earlier)
+    }
+}
+
+#[test_case]
+fn test_uptime_advances() {
+    let start = uptime();
+    let ticks = ticks();
+    while self::ticks() < ticks + 2 {
+        x86_64::instructions::hlt();
+    }
+    assert!(uptime() > start);
+}
+
+#[test_case]
+fn test_sleep() {
+    let start = Instant::now();
+    sleep(Duration::from_millis(20));
+    assert!(start.elapsed() >= Duration::from_millis(20));
+}
+
+#[test_case]
+fn test_instant_arithmetic() {
+    let start = Instant::now();
+    let later = start + Duration::from_millis(5);
+    assert_eq!(later - start, Duration::from_millis(5));
+    assert_eq!(start - later, Duration::ZERO);
+    assert!(later > start);
+}
+
+#[test_case]
+fn test_frequency_rounding() {
+    assert_eq!(set_frequency(1), PIT_BASE_FREQUENCY / 65536);
+    assert_eq!(set_frequency(DEFAULT_FREQUENCY), 1000);
+}
+
+#[cfg(test)]
+mod deadline_tests {
+    use super::{cancel_deadline, set_deadline, sleep, Duration, Instant};
+    use core::sync::atomic::{AtomicBool, Ordering};
+
+    static FIRED: AtomicBool = AtomicBool::new(false);
+
+    fn mark_fired() {
+        FIRED.store(true, Ordering::SeqCst);
+    }
+
+    #[test_case]
+    fn test_deadline_fires() {
+        FIRED.store(false, Ordering::SeqCst);
+        set_deadline(Instant::now() + Duration::from_millis(5), mark_fired)
+            .expect("Setting the deadline failed");
+        while !FIRED.load(Ordering::SeqCst) {
+            x86_64::instructions::hlt();
+        }
+    }
+
+    #[test_case]
+    fn test_cancel_deadline() {
+        FIRED.store(false, Ordering::SeqCst);
+        let id = set_deadline(Instant::now() + Duration::from_millis(5), mark_fired)
+            .expect("Setting the deadline failed");
+        assert!(cancel_deadline(id));
+        assert!(!cancel_deadline(id));
+        sleep(Duration::from_millis(10));
+        assert!(!FIRED.load(Ordering::SeqCst));
+    }
+}
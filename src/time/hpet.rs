@@ -0,0 +1,118 @@
+use crate::memory;
+use core::sync::atomic::{AtomicU64, Ordering};
+use x86_64::{
+    structures::paging::{mapper::MapToError, FrameAllocator, Mapper, Size4KiB},
+    PhysAddr,
+};
+
+/// Size of the register block of an HPET
+const REGISTERS_SIZE: u64 = 0x400;
+
+/// HPET register offsets
+const GENERAL_CAPABILITIES: usize = 0x000;
+const GENERAL_CONFIGURATION: usize = 0x010;
+const MAIN_COUNTER: usize = 0x0F0;
+/// Set in the capabilities when the main counter has 64 bits
+const COUNT_SIZE_64: u64 = 1 << 13;
+/// Starts the main counter in the general configuration
+const ENABLE: u64 = 1 << 0;
+
+/// The longest counter period the specification allows, 100 ns
+const MAX_PERIOD_FS: u64 = 100_000_000;
+
+/// Virtual address of the mapped registers, zero until `init` is done
+static REGISTERS: AtomicU64 = AtomicU64::new(0);
+/// Femtoseconds between two increments of the main counter
+static PERIOD_FS: AtomicU64 = AtomicU64::new(0);
+
+/// Errors returned by `init`
+#[derive(Debug)]
+pub enum HpetError {
+    /// The ACPI tables don't describe an HPET, or weren't read yet
+    NotPresent,
+    /// The registers could not be mapped
+    Map(MapToError<Size4KiB>),
+    /// The capabilities report a period the specification doesn't allow
+    InvalidPeriod(u64),
+    /// The main counter only has 32 bits and would wrap within minutes
+    NarrowCounter,
+}
+
+/// Maps the HPET found in the ACPI tables and starts its main counter
+///
+/// # Errors
+///
+/// Fails if there is no HPET or it could not be mapped.
+pub fn init(
+    mapper: &mut impl Mapper<Size4KiB>,
+    frame_allocator: &mut impl FrameAllocator<Size4KiB>,
+) -> Result<(), HpetError> {
+    if is_available() {
+        return Ok(());
+    }
+
+    let hpet = crate::acpi::tables()
+        .and_then(|tables| tables.hpet.as_ref())
+        .ok_or(HpetError::NotPresent)?;
+    let registers = unsafe {
+        memory::map_mmio(
+            PhysAddr::new(hpet.address),
+            REGISTERS_SIZE,
+            mapper,
+            frame_allocator,
+        )
+        .map_err(HpetError::Map)?
+    };
+    REGISTERS.store(registers.as_u64(), Ordering::SeqCst);
+
+    let capabilities = unsafe { read(GENERAL_CAPABILITIES) };
+    let period = capabilities >> 32;
+    if period == 0 || period > MAX_PERIOD_FS {
+        return Err(HpetError::InvalidPeriod(period));
+    }
+    if capabilities & COUNT_SIZE_64 == 0 {
+        return Err(HpetError::NarrowCounter);
+    }
+
+    unsafe {
+        let configuration = read(GENERAL_CONFIGURATION);
+        write(GENERAL_CONFIGURATION, configuration | ENABLE);
+    }
+    PERIOD_FS.store(period, Ordering::SeqCst);
+
+    Ok(())
+}
+
+/// Whether `init` succeeded
+#[must_use]
+pub fn is_available() -> bool {
+    PERIOD_FS.load(Ordering::SeqCst) != 0
+}
+
+/// Returns the main counter, `None` before `init`
+#[must_use]
+pub fn counter() -> Option<u64> {
+    is_available().then(|| unsafe { read(MAIN_COUNTER) })
+}
+
+/// Returns the frequency of the main counter in Hz, `None` before `init`
+#[must_use]
+pub fn frequency_hz() -> Option<u64> {
+    is_available().then(|| 1_000_000_000_000_000 / PERIOD_FS.load(Ordering::SeqCst))
+}
+
+/// Converts a number of counter increments into nanoseconds
+#[must_use]
+pub fn counter_to_nanos(counter: u64) -> u64 {
+    (u128::from(counter) * u128::from(PERIOD_FS.load(Ordering::SeqCst)) / 1_000_000) as u64
+}
+
+unsafe fn read(register: usize) -> u64 {
+    let base = REGISTERS.load(Ordering::SeqCst) as usize;
+    unsafe { core::ptr::read_volatile((base + register) as *const u64) }
+}
+
+unsafe fn write(register: usize, value: u64) {
+    let base = REGISTERS.load(Ordering::SeqCst) as usize;
+    unsafe { core::ptr::write_volatile((base + register) as *mut u64, value) };
+}
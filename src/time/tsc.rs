@@ -0,0 +1,73 @@
+use core::sync::atomic::{AtomicU64, Ordering};
+
+/// Calibrated frequency of the time stamp counter, zero until it is calibrated
+static FREQUENCY: AtomicU64 = AtomicU64::new(0);
+
+/// Reads the time stamp counter
+#[must_use]
+pub fn read() -> u64 {
+    unsafe { core::arch::x86_64::_rdtsc() }
+}
+
+/// Checks CPUID for a time stamp counter that ticks at the same rate in every power state
+#[must_use]
+pub fn is_invariant() -> bool {
+    let extended = core::arch::x86_64::__cpuid(0x8000_0000).eax;
+    extended >= 0x8000_0007 && core::arch::x86_64::__cpuid(0x8000_0007).edx & (1 << 8) != 0
+}
+
+/// Returns the calibrated frequency in Hz, `None` before the calibration
+#[must_use]
+pub fn frequency_hz() -> Option<u64> {
+    match FREQUENCY.load(Ordering::SeqCst) {
+        0 => None,
+        frequency => Some(frequency),
+    }
+}
+
+/// Measures the frequency against the HPET or, without one, against the PIT ticks
+///
+/// The HPET is polled for 10 ms. The PIT needs interrupts enabled and takes 50 ticks.
+pub(super) fn calibrate() -> u64 {
+    let frequency = match super::hpet::frequency_hz() {
+        Some(hpet_frequency) => calibrate_against_hpet(hpet_frequency),
+        None => calibrate_against_pit(),
+    };
+    FREQUENCY.store(frequency, Ordering::SeqCst);
+    frequency
+}
+
+fn calibrate_against_hpet(hpet_frequency: u64) -> u64 {
+    let counter = || super::hpet::counter().unwrap_or_default();
+    let window = hpet_frequency / 100;
+
+    let hpet_start = counter();
+    let tsc_start = read();
+    let mut hpet_end = hpet_start;
+    while hpet_end.wrapping_sub(hpet_start) < window {
+        core::hint::spin_loop();
+        hpet_end = counter();
+    }
+    let tsc_end = read();
+
+    let elapsed_nanos = super::hpet::counter_to_nanos(hpet_end.wrapping_sub(hpet_start));
+    (u128::from(tsc_end - tsc_start) * 1_000_000_000 / u128::from(elapsed_nanos)) as u64
+}
+
+fn calibrate_against_pit() -> u64 {
+    const TICKS: u64 = 50;
+
+    // Start right at a tick, so the window covers whole ticks
+    let first = super::ticks();
+    while super::ticks() == first {
+        x86_64::instructions::hlt();
+    }
+    let start = super::ticks();
+    let tsc_start = read();
+    while super::ticks() < start + TICKS {
+        x86_64::instructions::hlt();
+    }
+    let tsc_end = read();
+
+    (tsc_end - tsc_start) * u64::from(super::frequency_hz()) / TICKS
+}
@@ -0,0 +1,311 @@
+use super::{Duration, Instant};
+use alloc::{boxed::Box, sync::Arc, vec::Vec};
+use core::{
+    future::Future,
+    pin::Pin,
+    sync::atomic::{AtomicU8, Ordering},
+    task::{Context, Poll},
+};
+use futures_util::task::AtomicWaker;
+use spin::Mutex;
+use x86_64::instructions::interrupts::without_interrupts;
+
+/// Every level splits its range into `1 << LEVEL_BITS` slots
+const LEVEL_BITS: u32 = 6;
+const SLOTS: usize = 1 << LEVEL_BITS;
+/// Level 0 has slots of 1 ms, every further level 64 times longer, so the wheel spans ~4.6 h
+const LEVELS: usize = 4;
+
+/// States of a timeout shared between the wheel and its `TimeoutHandle`
+const PENDING: u8 = 0;
+const FIRED: u8 = 1;
+const CANCELLED: u8 = 2;
+
+/// The wheel, advanced by the timer interrupt
+///
+/// It must only be locked with interrupts disabled.
+static WHEEL: Mutex<Wheel> = Mutex::new(Wheel::new());
+
+/// A callback waiting in the wheel
+struct Entry {
+    /// Millisecond since boot it expires at
+    expires: u64,
+    state: Arc<AtomicU8>,
+    callback: Box<dyn FnOnce() + Send>,
+}
+
+/// A hierarchical timer wheel with millisecond resolution
+///
+/// Level `n` covers the timeouts within the current block of `64^(n + 1)` ms and
+/// hashes them by the bits `6n..6(n + 1)` of their expiry. Whenever the time enters
+/// a new slot of a level, the entries of that slot cascade down to the finer levels,
+/// until they end up in level 0 and fire.
+struct Wheel {
+    levels: [[Vec<Entry>; SLOTS]; LEVELS],
+    /// Timeouts beyond the range of the top level, reinserted whenever its block changes
+    overflow: Vec<Entry>,
+    /// The last millisecond processed
+    current: u64,
+}
+
+impl Wheel {
+    const fn new() -> Self {
+        const EMPTY_SLOT: Vec<Entry> = Vec::new();
+        const EMPTY_LEVEL: [Vec<Entry>; SLOTS] = [EMPTY_SLOT; SLOTS];
+
+        Self {
+            levels: [EMPTY_LEVEL; LEVELS],
+            overflow: Vec::new(),
+            current: 0,
+        }
+    }
+
+    /// Adds a new timeout, one that already expired fires on the next millisecond
+    fn insert(&mut self, mut entry: Entry) {
+        entry.expires = entry.expires.max(self.current + 1);
+        self.place(entry);
+    }
+
+    /// Puts `entry` into the finest level covering its expiry
+    ///
+    /// Cascaded entries may expire in the current millisecond, which collects them
+    /// from level 0 right after the cascade.
+    fn place(&mut self, entry: Entry) {
+        for level in 0..LEVELS {
+            let block_shift = LEVEL_BITS * (level as u32 + 1);
+            if entry.expires >> block_shift == self.current >> block_shift {
+                let slot = (entry.expires >> (LEVEL_BITS * level as u32)) as usize % SLOTS;
+                self.levels[level][slot].push(entry);
+                return;
+            }
+        }
+        self.overflow.push(entry);
+    }
+
+    /// Processes every millisecond up to `now` and collects the expired entries into `expired`
+    fn advance(&mut self, now: u64, expired: &mut Vec<Entry>) {
+        while self.current < now {
+            self.current += 1;
+
+            // Cascade from the top, a reinserted entry may land in a slot cascaded next
+            if self
+                .current
+                .is_multiple_of(1 << (LEVEL_BITS * LEVELS as u32))
+            {
+                for entry in core::mem::take(&mut self.overflow) {
+                    self.place(entry);
+                }
+            }
+            for level in (1..LEVELS).rev() {
+                let shift = LEVEL_BITS * level as u32;
+                if !self.current.is_multiple_of(1 << shift) {
+                    continue;
+                }
+                let slot = (self.current >> shift) as usize % SLOTS;
+                for entry in core::mem::take(&mut self.levels[level][slot]) {
+                    self.place(entry);
+                }
+            }
+
+            let slot = self.current as usize % SLOTS;
+            expired.append(&mut self.levels[0][slot]);
+        }
+    }
+}
+
+/// A handle to cancel a timeout set with `set_timeout`
+///
+/// Dropping it doesn't cancel the timeout.
+#[derive(Debug, Clone)]
+pub struct TimeoutHandle {
+    state: Arc<AtomicU8>,
+}
+
+impl TimeoutHandle {
+    /// Cancels the timeout, returns `false` if it already fired or was cancelled
+    pub fn cancel(&self) -> bool {
+        self.state
+            .compare_exchange(PENDING, CANCELLED, Ordering::SeqCst, Ordering::SeqCst)
+            .is_ok()
+    }
+
+    /// Whether the timeout neither fired nor was cancelled yet
+    #[must_use]
+    pub fn is_pending(&self) -> bool {
+        self.state.load(Ordering::SeqCst) == PENDING
+    }
+}
+
+/// Calls `callback` from the timer interrupt once `duration` has passed
+///
+/// The callback runs with interrupts disabled, so it should only do as much work as
+/// an IRQ handler, like waking a task.
+pub fn set_timeout(duration: Duration, callback: impl FnOnce() + Send + 'static) -> TimeoutHandle {
+    let deadline = Instant::now() + duration;
+    // Round up, firing early is never fine
+    let expires = deadline.as_nanos().div_ceil(1_000_000);
+
+    let state = Arc::new(AtomicU8::new(PENDING));
+    let entry = Entry {
+        expires,
+        state: Arc::clone(&state),
+        callback: Box::new(callback),
+    };
+    without_interrupts(|| WHEEL.lock().insert(entry));
+
+    TimeoutHandle { state }
+}
+
+/// Fires the expired timeouts, called by the timer interrupt
+pub(super) fn advance() {
+    let now = Instant::now().as_nanos() / 1_000_000;
+
+    let mut expired = Vec::new();
+    WHEEL.lock().advance(now, &mut expired);
+
+    // The lock is released, so the callbacks can set new timeouts
+    for entry in expired {
+        if entry
+            .state
+            .compare_exchange(PENDING, FIRED, Ordering::SeqCst, Ordering::SeqCst)
+            .is_ok()
+        {
+            (entry.callback)();
+        }
+    }
+}
+
+/// Returns a future completing once `duration` has passed
+///
+/// Unlike `time::sleep` it only suspends the task, the executor keeps running others.
+pub fn sleep(duration: Duration) -> Sleep {
+    Sleep {
+        deadline: Instant::now() + duration,
+        waker: Arc::new(AtomicWaker::new()),
+        timeout: None,
+    }
+}
+
+/// The future returned by `sleep`
+pub struct Sleep {
+    deadline: Instant,
+    waker: Arc<AtomicWaker>,
+    /// Set on the first poll that has to wait
+    timeout: Option<TimeoutHandle>,
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, context: &mut Context) -> Poll<()> {
+        let now = Instant::now();
+        if now >= self.deadline {
+            return Poll::Ready(());
+        }
+
+        self.waker.register(context.waker());
+        if self.timeout.is_none() {
+            let waker = Arc::clone(&self.waker);
+            let timeout = set_timeout(self.deadline - now, move || waker.wake());
+            self.timeout = Some(timeout);
+        }
+        Poll::Pending
+    }
+}
+
+impl Drop for Sleep {
+    fn drop(&mut self) {
+        if let Some(timeout) = &self.timeout {
+            timeout.cancel();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{set_timeout, sleep, Duration, Entry, Instant, Wheel, PENDING};
+    use crate::task::{executor::Executor, Task};
+    use alloc::{boxed::Box, sync::Arc, vec::Vec};
+    use core::sync::atomic::{AtomicU8, AtomicUsize, Ordering};
+
+    static FIRED: AtomicUsize = AtomicUsize::new(0);
+
+    fn wait_for(duration: Duration) {
+        let deadline = Instant::now() + duration;
+        while Instant::now() < deadline {
+            x86_64::instructions::hlt();
+        }
+    }
+
+    #[test_case]
+    fn test_timeout_fires() {
+        FIRED.store(0, Ordering::SeqCst);
+        let start = Instant::now();
+        let handle = set_timeout(Duration::from_millis(5), move || {
+            assert!(start.elapsed() >= Duration::from_millis(5));
+            FIRED.fetch_add(1, Ordering::SeqCst);
+        });
+        wait_for(Duration::from_millis(10));
+        assert_eq!(FIRED.load(Ordering::SeqCst), 1);
+        assert!(!handle.is_pending());
+        assert!(!handle.cancel());
+    }
+
+    #[test_case]
+    fn test_cancelled_timeout_does_not_fire() {
+        FIRED.store(0, Ordering::SeqCst);
+        let handle = set_timeout(Duration::from_millis(5), || {
+            FIRED.fetch_add(1, Ordering::SeqCst);
+        });
+        assert!(handle.cancel());
+        wait_for(Duration::from_millis(10));
+        assert_eq!(FIRED.load(Ordering::SeqCst), 0);
+    }
+
+    #[test_case]
+    fn test_timeout_cascades_from_upper_level() {
+        // Longer than the 64 ms of level 0, so it has to cascade at least once
+        FIRED.store(0, Ordering::SeqCst);
+        set_timeout(Duration::from_millis(100), || {
+            FIRED.fetch_add(1, Ordering::SeqCst);
+        });
+        wait_for(Duration::from_millis(90));
+        assert_eq!(FIRED.load(Ordering::SeqCst), 0);
+        wait_for(Duration::from_millis(20));
+        assert_eq!(FIRED.load(Ordering::SeqCst), 1);
+    }
+
+    #[test_case]
+    fn test_timeout_on_cascade_boundary() {
+        let mut wheel = Wheel::new();
+        // Starts in level 1, as it is past the first 64 ms block
+        wheel.insert(Entry {
+            expires: 64,
+            state: Arc::new(AtomicU8::new(PENDING)),
+            callback: Box::new(|| {}),
+        });
+
+        let mut expired = Vec::new();
+        wheel.advance(63, &mut expired);
+        assert!(expired.is_empty());
+        wheel.advance(64, &mut expired);
+        assert_eq!(expired.len(), 1);
+    }
+
+    #[test_case]
+    fn test_async_sleep() {
+        FIRED.store(0, Ordering::SeqCst);
+        let start = Instant::now();
+        let mut executor = Executor::new();
+        executor.spawn(Task::new(async {
+            sleep(Duration::from_millis(20)).await;
+            FIRED.fetch_add(1, Ordering::SeqCst);
+        }));
+        while !executor.is_empty() {
+            executor.run_until_idle();
+            x86_64::instructions::hlt();
+        }
+        assert_eq!(FIRED.load(Ordering::SeqCst), 1);
+        assert!(start.elapsed() >= Duration::from_millis(20));
+    }
+}
@@ -1,3 +1,15 @@
+use ansi::{Action, Erase, Parser};
+use scrollback::Scrollback;
+
+/// Parser of the VT100 escape sequences in the output
+mod ansi;
+/// Virtual consoles sharing the screen
+pub mod console;
+/// Translation of Unicode into the character set of the VGA font
+pub mod cp437;
+/// History of the lines that scrolled off the screen
+mod scrollback;
+
 /// A helper struct with color codes defined
 #[allow(dead_code)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -31,18 +43,130 @@ impl ColorCode {
     const fn new(foreground: Color, background: Color) -> Self {
         Self((background as u8) << 4 | (foreground as u8))
     }
+
+    const fn foreground(self) -> u8 {
+        self.0 & 0x0f
+    }
+
+    const fn background(self) -> u8 {
+        self.0 >> 4
+    }
+
+    const fn with_foreground(self, color: u8) -> Self {
+        Self(self.0 & 0xf0 | color & 0x0f)
+    }
+
+    const fn with_background(self, color: u8) -> Self {
+        Self((color & 0x0f) << 4 | self.0 & 0x0f)
+    }
 }
 
+/// Colors of the text until an escape sequence changes them
+const DEFAULT_COLOR: ColorCode = ColorCode::new(Color::Red, Color::Black);
+/// Makes a foreground color bright, bold text uses it
+const BRIGHT: u8 = 0x08;
+
+/// The VGA colors of the ANSI colors 0 to 7, their bright variants add `BRIGHT`
+const ANSI_COLORS: [Color; 8] = [
+    Color::Black,
+    Color::Red,
+    Color::Green,
+    Color::Brown,
+    Color::Blue,
+    Color::Magenta,
+    Color::Cyan,
+    Color::LightGray,
+];
+
 /// A representation of a character to be printed on the VGA
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(C)]
-struct ScreenChar {
+pub(crate) struct ScreenChar {
     ascii_character: u8,
     color_code: ColorCode,
 }
 
-const BUFFER_HEIGHT: usize = 25;
-const BUFFER_WIDTH: usize = 80;
+impl ScreenChar {
+    /// An empty cell, what the zeroed memory of an off-screen buffer contains
+    pub(crate) const ZERO: Self = Self {
+        ascii_character: 0,
+        color_code: ColorCode(0),
+    };
+
+    /// The byte of the character in code page 437
+    pub(crate) const fn byte(self) -> u8 {
+        self.ascii_character
+    }
+
+    /// The foreground and background color as indices into the 16 VGA colors
+    pub(crate) const fn colors(self) -> (u8, u8) {
+        (self.color_code.foreground(), self.color_code.background())
+    }
+}
+
+/// Number of text rows on the screen
+pub const BUFFER_HEIGHT: usize = 25;
+/// Number of text columns on the screen
+pub const BUFFER_WIDTH: usize = 80;
+/// Most rows any screen of a `Writer` may have, the framebuffer can have more than text mode
+pub const MAX_ROWS: usize = 64;
+/// Most columns any screen of a `Writer` may have
+pub const MAX_COLUMNS: usize = 160;
+/// A tab moves the cursor to the next multiple of this column
+pub const TAB_WIDTH: usize = 8;
+/// Number of lines kept after they scrolled off the screen
+pub const SCROLLBACK_LINES: usize = 256;
+
+/// Physical and, identity mapped by the bootloader, virtual address of the text buffer
+const VGA_BUFFER: usize = 0xb8000;
+
+/// Index and data ports of the CRT controller
+const CRTC_INDEX: u16 = 0x3D4;
+const CRTC_DATA: u16 = 0x3D5;
+/// CRTC registers of the cursor shape and location
+const CURSOR_START: u8 = 0x0A;
+const CURSOR_END: u8 = 0x0B;
+const CURSOR_LOCATION_HIGH: u8 = 0x0E;
+const CURSOR_LOCATION_LOW: u8 = 0x0F;
+/// Set in `CURSOR_START` to hide the cursor
+const CURSOR_DISABLE: u8 = 1 << 5;
+/// Scanlines of the underline cursor within the 16 of a character
+const CURSOR_SCANLINES: (u8, u8) = (14, 15);
+
+/// A grid of character cells a `Writer` draws into
+///
+/// Implemented by the text mode buffer, the off-screen copies of the consoles and
+/// the framebuffer console.
+pub(crate) trait TextBuffer: Send {
+    /// Number of rows and columns, at most `MAX_ROWS` and `MAX_COLUMNS`
+    fn size(&self) -> (usize, usize);
+
+    fn read(&self, row: usize, col: usize) -> ScreenChar;
+
+    fn write(&mut self, row: usize, col: usize, screen_char: ScreenChar);
+
+    /// Whether the cells are on the screen
+    fn is_screen(&self) -> bool {
+        false
+    }
+
+    /// Moves the cursor to `position`, `None` hides it
+    fn show_cursor(&mut self, _position: Option<(usize, usize)>) {}
+
+    /// Draws every cell again, after something else drew over the screen
+    fn redraw(&mut self) {}
+
+    /// Moves every row one up, the last one keeps its characters
+    fn shift_up(&mut self) {
+        let (rows, cols) = self.size();
+        for row in 1..rows {
+            for col in 0..cols {
+                let character = self.read(row, col);
+                self.write(row - 1, col, character);
+            }
+        }
+    }
+}
 
 /// The VGA buffer
 #[repr(transparent)]
@@ -50,72 +174,373 @@ struct Buffer {
     chars: [[volatile::Volatile<ScreenChar>; BUFFER_WIDTH]; BUFFER_HEIGHT],
 }
 
+impl TextBuffer for Buffer {
+    fn size(&self) -> (usize, usize) {
+        (BUFFER_HEIGHT, BUFFER_WIDTH)
+    }
+
+    fn read(&self, row: usize, col: usize) -> ScreenChar {
+        self.chars[row][col].read()
+    }
+
+    fn write(&mut self, row: usize, col: usize, screen_char: ScreenChar) {
+        self.chars[row][col].write(screen_char);
+    }
+
+    fn is_screen(&self) -> bool {
+        core::ptr::eq(self, VGA_BUFFER as *const Self)
+    }
+
+    fn show_cursor(&mut self, position: Option<(usize, usize)>) {
+        if !self.is_screen() {
+            return;
+        }
+        // Past the end of the screen it's hidden
+        let location = match position {
+            Some((row, col)) => (row * BUFFER_WIDTH + col) as u16,
+            None => (BUFFER_HEIGHT * BUFFER_WIDTH) as u16,
+        };
+        unsafe {
+            write_crtc(CURSOR_LOCATION_HIGH, (location >> 8) as u8);
+            write_crtc(CURSOR_LOCATION_LOW, location as u8);
+        }
+    }
+}
+
 /// A writer to actually write to the screen
+///
+/// Understands the VT100 sequences for colors (`CSI ... m`), cursor movement
+/// (`CSI A`-`D`, `CSI H`) and clearing (`CSI J`, `CSI K`).
 pub struct Writer {
     column_position: usize,
+    row_position: usize,
     color_code: ColorCode,
-    buffer: &'static mut Buffer,
+    /// Set by SGR 1, the foreground colors are bright until SGR 22 or 0
+    bold: bool,
+    parser: Parser,
+    /// `None` for the writer used while `WRITER` is locked
+    scrollback: Option<&'static mut Scrollback<SCROLLBACK_LINES>>,
+    buffer: &'static mut dyn TextBuffer,
 }
 
 impl Writer {
-    /// A method that writes a single byte to the screen
+    /// A second writer for the screen, used while `WRITER` is locked by the code we interrupted
+    ///
+    /// It draws on the screen the consoles are shown on, starting on a new line at its
+    /// bottom, so the interrupted line stays intact. `None` while the screen is replaced.
+    fn bypassing_lock() -> Option<Self> {
+        let screen = SCREEN.try_lock()?;
+        let mut writer = Self::new(None);
+        if let Some(ScreenPointer(screen)) = *screen {
+            // Shared with the interrupted writer, like the VGA buffer is
+            writer.buffer = unsafe { &mut *screen };
+            let (rows, _) = writer.size();
+            writer.row_position = rows - 1;
+        }
+        writer.new_line();
+        Some(writer)
+    }
+
+    /// Creates a writer for the VGA buffer, starting at the bottom row
+    fn new(scrollback: Option<&'static mut Scrollback<SCROLLBACK_LINES>>) -> Self {
+        Self {
+            column_position: 0,
+            row_position: BUFFER_HEIGHT - 1,
+            color_code: DEFAULT_COLOR,
+            bold: false,
+            parser: Parser::new(),
+            scrollback,
+            buffer: unsafe { &mut *(VGA_BUFFER as *mut Buffer) },
+        }
+    }
+
+    /// Returns the number of rows and columns of the screen
+    #[must_use]
+    pub fn size(&self) -> (usize, usize) {
+        self.buffer.size()
+    }
+
+    /// Scrolls the view up into the history by `lines`, as far as it goes
+    pub fn scroll_up(&mut self, lines: usize) {
+        if let Some(scrollback) = &mut self.scrollback {
+            let offset = scrollback.offset().saturating_add(lines);
+            scrollback.scroll_to(self.buffer, offset);
+        }
+        self.update_cursor();
+    }
+
+    /// Scrolls the view down towards the live screen by `lines`
+    pub fn scroll_down(&mut self, lines: usize) {
+        if let Some(scrollback) = &mut self.scrollback {
+            let offset = scrollback.offset().saturating_sub(lines);
+            scrollback.scroll_to(self.buffer, offset);
+        }
+        self.update_cursor();
+    }
+
+    /// Returns how many lines the view is scrolled up into the history
+    #[must_use]
+    pub fn scroll_offset(&self) -> usize {
+        self.scrollback
+            .as_ref()
+            .map_or(0, |scrollback| scrollback.offset())
+    }
+
+    /// Number of lines in the history
+    #[must_use]
+    pub fn scrollback_len(&self) -> usize {
+        self.scrollback
+            .as_ref()
+            .map_or(0, |scrollback| scrollback.len())
+    }
+
+    /// Shows the live screen again, everything that writes to the screen calls this first
+    fn snap_back(&mut self) {
+        if let Some(scrollback) = &mut self.scrollback {
+            scrollback.scroll_to(self.buffer, 0);
+        }
+    }
+
+    /// Moves the writer to `buffer` and copies the screen there, returns the old buffer
+    ///
+    /// The part of the screen that doesn't fit is lost, a bigger one is blank.
+    fn replace_buffer(
+        &mut self,
+        buffer: &'static mut dyn TextBuffer,
+    ) -> &'static mut dyn TextBuffer {
+        self.snap_back();
+        let (rows, cols) = buffer.size();
+        let (old_rows, old_cols) = self.size();
+        let blank = self.blank();
+        for row in 0..rows {
+            for col in 0..cols {
+                let character = if row < old_rows && col < old_cols {
+                    self.buffer.read(row, col)
+                } else {
+                    blank
+                };
+                buffer.write(row, col, character);
+            }
+        }
+
+        let old = core::mem::replace(&mut self.buffer, buffer);
+        old.show_cursor(None);
+        self.row_position = self.row_position.min(rows - 1);
+        self.column_position = self.column_position.min(cols);
+        self.update_cursor();
+        old
+    }
+
+    /// Returns the row and column the next character is written to
+    #[must_use]
+    pub fn position(&self) -> (usize, usize) {
+        let (_, cols) = self.size();
+        (self.row_position, self.column_position.min(cols - 1))
+    }
+
+    /// Moves the cursor anywhere on the screen, the position is clamped to it
+    pub fn set_position(&mut self, row: usize, col: usize) {
+        let (rows, cols) = self.size();
+        self.row_position = row.min(rows - 1);
+        self.column_position = col.min(cols - 1);
+        self.update_cursor();
+    }
+
+    /// A method that writes a single byte to the screen, non-ASCII bytes are code page 437
+    ///
+    /// Handles `\n`, `\r`, `\t` and backspace, which only moves the cursor back.
     pub fn write_byte(&mut self, byte: u8) {
+        self.snap_back();
+        let (_, cols) = self.size();
         match byte {
             // If the byte is newline, continue printing to the new line
             b'\n' => self.new_line(),
-            byte => {
-                // If the line is full, continue printing to the new line
-                if self.column_position >= BUFFER_WIDTH {
-                    self.new_line();
+            b'\r' => self.column_position = 0,
+            b'\t' => {
+                for _ in 0..TAB_WIDTH - self.column_position % TAB_WIDTH {
+                    self.write_byte(b' ');
                 }
-
-                let row = BUFFER_HEIGHT - 1;
-                let col = self.column_position;
-
-                let color_code = self.color_code;
-                self.buffer.chars[row][col].write(ScreenChar {
-                    ascii_character: byte,
-                    color_code,
-                });
-                self.column_position += 1;
             }
+            // Backspace, continues at the end of the line above like after a wrap
+            0x08 => match self.column_position {
+                0 if self.row_position > 0 => {
+                    self.row_position -= 1;
+                    self.column_position = cols - 1;
+                }
+                0 => {}
+                col => self.column_position = col.min(cols) - 1,
+            },
+            byte => self.write_glyph(byte),
         }
     }
 
-    /// A method that writes the whole string to the VGA
+    /// Draws the code page 437 glyph of `byte` at the cursor and advances it
+    ///
+    /// Unlike `write_byte`, the glyphs sharing their byte with a control character,
+    /// like `◘` and `◙`, are drawn as well.
+    fn write_glyph(&mut self, byte: u8) {
+        let (_, cols) = self.size();
+        // If the line is full, continue printing to the new line
+        if self.column_position >= cols {
+            self.new_line();
+        }
+
+        let row = self.row_position;
+        let col = self.column_position;
+
+        let color_code = self.color_code;
+        self.buffer.write(
+            row,
+            col,
+            ScreenChar {
+                ascii_character: byte,
+                color_code,
+            },
+        );
+        self.column_position += 1;
+    }
+
+    /// A method that writes the whole string to the VGA, interpreting the escape sequences
     pub fn write_string(&mut self, s: &str) {
-        for byte in s.bytes() {
-            match byte {
-                // Print the byte if it is part of the ASCII table
-                0x20..=0x7e | b'\n' => self.write_byte(byte),
-                // If not, just print ■
-                _ => self.write_byte(0xfe),
+        self.snap_back();
+        for c in s.chars() {
+            match self.parser.advance(c) {
+                // The supported controls
+                Action::Print(c @ ('\n' | '\r' | '\t' | '\x08')) => self.write_byte(c as u8),
+                // Print the character if the font has it, if not, just print ■
+                Action::Print(c) => {
+                    self.write_glyph(cp437::encode(c).unwrap_or(cp437::REPLACEMENT))
+                }
+                action => self.perform(action),
             }
         }
+        // Once per string, the ports are slow
+        self.update_cursor();
     }
 
-    /// Moves every character one line up
-    fn new_line(&mut self) {
-        // We ommit the first row, because it is shifted off screen
-        for row in 1..BUFFER_HEIGHT {
-            for col in 0..BUFFER_WIDTH {
-                let character = self.buffer.chars[row][col].read();
-                self.buffer.chars[row - 1][col].write(character);
+    /// Moves the cursor of the screen to the position of the writer
+    fn update_cursor(&mut self) {
+        // The history has no cursor
+        let position = (self.scroll_offset() == 0).then(|| self.position());
+        self.buffer.show_cursor(position);
+    }
+
+    /// Carries out an escape sequence
+    fn perform(&mut self, action: Action) {
+        let (rows, cols) = self.size();
+        // A full line leaves the cursor past the last column until the next character
+        let col = self.column_position.min(cols - 1);
+        match action {
+            Action::Print(_) | Action::None => {}
+            Action::CursorUp(count) => self.row_position = self.row_position.saturating_sub(count),
+            Action::CursorDown(count) => {
+                self.row_position = (self.row_position + count).min(rows - 1);
+            }
+            Action::CursorForward(count) => {
+                self.column_position = (col + count).min(cols - 1);
+            }
+            Action::CursorBack(count) => self.column_position = col.saturating_sub(count),
+            Action::CursorPosition { row, col } => self.set_position(row, col),
+            Action::EraseDisplay(erase) => {
+                let row = self.row_position;
+                let rows = match erase {
+                    Erase::ToEnd => row + 1..rows,
+                    Erase::ToStart => 0..row,
+                    Erase::All => 0..rows,
+                };
+                for row in rows {
+                    self.clean_row(row);
+                }
+                if erase != Erase::All {
+                    self.perform(Action::EraseLine(erase));
+                }
+            }
+            Action::EraseLine(erase) => {
+                let cols = match erase {
+                    Erase::ToEnd => col..cols,
+                    Erase::ToStart => 0..col + 1,
+                    Erase::All => 0..cols,
+                };
+                self.clean_cols(self.row_position, cols);
+            }
+            Action::SelectGraphicRendition(params) => {
+                // `CSI m` is the same as `CSI 0 m`
+                if params.as_slice().is_empty() {
+                    self.select_graphic_rendition(0);
+                }
+                for &param in params.as_slice() {
+                    self.select_graphic_rendition(param);
+                }
             }
         }
-        self.clean_row(BUFFER_HEIGHT - 1);
+    }
+
+    /// Applies a single SGR parameter, the unsupported ones are ignored
+    fn select_graphic_rendition(&mut self, param: u16) {
+        let bright = if self.bold { BRIGHT } else { 0 };
+        let ansi_color = |base: u16| ANSI_COLORS[usize::from(param - base)] as u8;
+        let color = self.color_code;
+
+        self.color_code = match param {
+            0 => {
+                self.bold = false;
+                DEFAULT_COLOR
+            }
+            1 => {
+                self.bold = true;
+                color.with_foreground(color.foreground() | BRIGHT)
+            }
+            22 => {
+                self.bold = false;
+                color.with_foreground(color.foreground() & !BRIGHT)
+            }
+            30..=37 => color.with_foreground(ansi_color(30) | bright),
+            39 => color.with_foreground(DEFAULT_COLOR.foreground() | bright),
+            40..=47 => color.with_background(ansi_color(40)),
+            49 => color.with_background(DEFAULT_COLOR.background()),
+            90..=97 => color.with_foreground(ansi_color(90) | BRIGHT),
+            100..=107 => color.with_background(ansi_color(100) | BRIGHT),
+            _ => color,
+        };
+    }
+
+    /// Moves the cursor to the next line, scrolling the screen at the bottom
+    fn new_line(&mut self) {
+        let (rows, _) = self.size();
         self.column_position = 0;
+        if self.row_position < rows - 1 {
+            self.row_position += 1;
+            return;
+        }
+
+        if let Some(scrollback) = &mut self.scrollback {
+            scrollback.push(&*self.buffer, 0);
+        }
+
+        // The first row is shifted off screen
+        self.buffer.shift_up();
+        self.clean_row(rows - 1);
     }
 
-    /// A method to write the row with spaces (so it appears empty)
-    fn clean_row(&mut self, row: usize) {
-        let blank = ScreenChar {
+    /// A space in the current colors
+    const fn blank(&self) -> ScreenChar {
+        ScreenChar {
             ascii_character: b' ',
             color_code: self.color_code,
-        };
+        }
+    }
+
+    /// A method to write the row with spaces (so it appears empty)
+    fn clean_row(&mut self, row: usize) {
+        let (_, cols) = self.size();
+        self.clean_cols(row, 0..cols);
+    }
 
-        for col in 0..BUFFER_WIDTH {
-            self.buffer.chars[row][col].write(blank);
+    /// Fills the columns `cols` of `row` with spaces in the current colors
+    fn clean_cols(&mut self, row: usize, cols: core::ops::Range<usize>) {
+        let blank = self.blank();
+        for col in cols {
+            self.buffer.write(row, col, blank);
         }
     }
 }
@@ -128,16 +553,89 @@ impl fmt::Write for Writer {
     }
 }
 
+use core::sync::atomic::{AtomicUsize, Ordering};
 use spin::Mutex;
+use x86_64::instructions::interrupts;
+
+/// Prints that found `WRITER` locked
+static REENTRANT_PRINTS: AtomicUsize = AtomicUsize::new(0);
+/// The screen after it moved out of the VGA buffer, for the writer bypassing `WRITER`
+static SCREEN: Mutex<Option<ScreenPointer>> = Mutex::new(None);
+/// The history of `WRITER`, too big for the stack of the writer used when it is locked
+static mut SCROLLBACK: Scrollback<SCROLLBACK_LINES> = Scrollback::new();
+
+/// A screen other than the VGA buffer, like the framebuffer console
+struct ScreenPointer(*mut dyn TextBuffer);
+
+// The screens are leaked and only used with interrupts disabled
+unsafe impl Send for ScreenPointer {}
+
 lazy_static::lazy_static! {
-    /// Sets up the writer with red color on black background at the VGA buffer address
-    pub static ref WRITER: Mutex<Writer> = Mutex::new(Writer {
-        column_position: 0,
-        color_code: ColorCode::new(Color::Red, Color::Black),
-        buffer: unsafe { &mut *(0xb8000 as *mut Buffer) },
+    /// The writer of the kernel console, red on black at the VGA buffer address
+    pub static ref WRITER: Mutex<Writer> = {
+        // Only this writer ever uses the scrollback, and it is created once
+        let scrollback = unsafe { &mut *core::ptr::addr_of_mut!(SCROLLBACK) };
+        Mutex::new(Writer::new(Some(scrollback)))
+    };
+}
+
+/// Returns the writer of the console on the screen
+fn active_writer() -> &'static Mutex<Writer> {
+    console::writer(console::active()).unwrap_or(&WRITER)
+}
+
+/// Scrolls the screen up into the history, bound to Shift+PageUp
+pub fn scroll_up(lines: usize) {
+    interrupts::without_interrupts(|| active_writer().lock().scroll_up(lines));
+}
+
+/// Scrolls the screen down towards the live output, bound to Shift+PageDown
+pub fn scroll_down(lines: usize) {
+    interrupts::without_interrupts(|| active_writer().lock().scroll_down(lines));
+}
+
+/// Writes `value` to the CRTC register `register`
+///
+/// # Safety
+///
+/// The value must be valid for the register.
+unsafe fn write_crtc(register: u8, value: u8) {
+    use x86_64::instructions::port::Port;
+
+    unsafe {
+        Port::new(CRTC_INDEX).write(register);
+        Port::new(CRTC_DATA).write(value);
+    }
+}
+
+/// Reads the CRTC register `register`
+fn read_crtc(register: u8) -> u8 {
+    use x86_64::instructions::port::Port;
+
+    unsafe {
+        Port::new(CRTC_INDEX).write(register);
+        Port::new(CRTC_DATA).read()
+    }
+}
+
+/// Shows the hardware cursor as an underline at the position of the shown console
+pub fn enable_cursor() {
+    let (start, end) = CURSOR_SCANLINES;
+    interrupts::without_interrupts(|| {
+        // The upper bits of both registers are unrelated to the shape
+        unsafe {
+            write_crtc(CURSOR_START, read_crtc(CURSOR_START) & 0xc0 | start);
+            write_crtc(CURSOR_END, read_crtc(CURSOR_END) & 0xe0 | end);
+        }
+        active_writer().lock().update_cursor();
     });
 }
 
+/// Hides the hardware cursor
+pub fn disable_cursor() {
+    interrupts::without_interrupts(|| unsafe { write_crtc(CURSOR_START, CURSOR_DISABLE) });
+}
+
 /// Prints to the VGA buffer
 #[macro_export]
 macro_rules! print {
@@ -153,18 +651,50 @@ macro_rules! println {
 
 #[doc(hidden)]
 pub fn _print(args: fmt::Arguments) {
+    crate::dmesg::write_fmt(args);
+    write_fmt(args);
+}
+
+/// Prints to the VGA buffer without keeping a copy in the kernel log
+pub(crate) fn write_fmt(args: fmt::Arguments) {
     use core::fmt::Write;
-    use x86_64::instructions::interrupts;
 
     interrupts::without_interrupts(|| {
-        // Panic on error, else continue
-        assert!(
-            WRITER.lock().write_fmt(args).is_ok(),
-            "Error writing to screen"
-        );
+        // With interrupts disabled, the lock can only be held by code we interrupted
+        // on this CPU, which won't continue before we return
+        if let Some(mut writer) = WRITER.try_lock() {
+            let _ = writer.write_fmt(args);
+        } else {
+            REENTRANT_PRINTS.fetch_add(1, Ordering::Relaxed);
+            match Writer::bypassing_lock() {
+                Some(mut writer) => {
+                    let _ = writer.write_fmt(args);
+                }
+                // Interrupted while moving the screen, the text isn't lost at least there
+                None => crate::serial::write_fmt(args),
+            }
+        }
     });
 }
 
+/// Number of prints that interrupted another print and bypassed `WRITER`
+#[must_use]
+pub fn reentrant_prints() -> usize {
+    REENTRANT_PRINTS.load(Ordering::Relaxed)
+}
+
+/// Releases `WRITER` and the writers of the other consoles no matter who holds them
+///
+/// # Safety
+///
+/// The holders must never use the writers again, like the code interrupted by a panic.
+pub unsafe fn force_unlock() {
+    unsafe {
+        WRITER.force_unlock();
+        console::force_unlock();
+    }
+}
+
 // Tests
 
 #[test_case]
@@ -182,14 +712,13 @@ fn test_printn_many() {
 #[test_case]
 fn test_println_output() {
     use core::fmt::Write;
-    use x86_64::instructions::interrupts;
 
     let s = "Some test string that fits on a single line";
     interrupts::without_interrupts(|| {
         let mut writer = WRITER.lock();
         writeln!(writer, "\n{s}").expect("writeln failed");
         for (i, c) in s.chars().enumerate() {
-            let screen_char = WRITER.lock().buffer.chars[BUFFER_HEIGHT - 2][i].read();
+            let screen_char = writer.buffer.read(BUFFER_HEIGHT - 2, i);
             assert_eq!(char::from(screen_char.ascii_character), c);
         }
     })
@@ -202,10 +731,153 @@ fn test_println_long_output() {
     println!("{s}");
     for (i, c) in s.chars().enumerate() {
         let screen_char = if i < BUFFER_WIDTH {
-            WRITER.lock().buffer.chars[BUFFER_HEIGHT - 3][i].read()
+            WRITER.lock().buffer.read(BUFFER_HEIGHT - 3, i)
         } else {
-            WRITER.lock().buffer.chars[BUFFER_HEIGHT - 2][i - BUFFER_WIDTH].read()
+            WRITER
+                .lock()
+                .buffer
+                .read(BUFFER_HEIGHT - 2, i - BUFFER_WIDTH)
         };
         assert_eq!(char::from(screen_char.ascii_character), c);
     }
 }
+
+#[test_case]
+fn test_print_while_writer_is_locked() {
+    let before = reentrant_prints();
+    interrupts::without_interrupts(|| {
+        // Like an NMI arriving in the middle of a print
+        let _writer = WRITER.lock();
+        println!("test_print_while_writer_is_locked output");
+    });
+    assert_eq!(reentrant_prints(), before + 1);
+}
+
+#[test_case]
+fn test_graphic_rendition() {
+    use core::fmt::Write;
+
+    interrupts::without_interrupts(|| {
+        let mut writer = WRITER.lock();
+        write!(writer, "\n\x1b[32;44mg\x1b[1mb\x1b[93;49my\x1b[0md").expect("write failed");
+        let row = |col| writer.buffer.read(BUFFER_HEIGHT - 1, col);
+        let colors = [
+            ColorCode::new(Color::Green, Color::Blue),
+            ColorCode::new(Color::LightGreen, Color::Blue),
+            ColorCode::new(Color::Yellow, Color::Black),
+            DEFAULT_COLOR,
+        ];
+        for (col, color_code) in colors.into_iter().enumerate() {
+            assert_eq!(row(col).color_code, color_code);
+        }
+        assert_eq!(row(1).ascii_character, b'b');
+    });
+}
+
+#[test_case]
+fn test_cursor_movement_and_erase() {
+    use core::fmt::Write;
+
+    interrupts::without_interrupts(|| {
+        let mut writer = WRITER.lock();
+        write!(writer, "\nabcdef\x1b[3D\x1b[Kx\x1b[2;5Hy").expect("write failed");
+        let bottom = |col| writer.buffer.read(BUFFER_HEIGHT - 1, col);
+        let text: [u8; 6] = core::array::from_fn(|col| bottom(col).ascii_character);
+        assert_eq!(&text, b"abcx  ");
+        assert_eq!(writer.buffer.read(1, 4).ascii_character, b'y');
+
+        // Leave the cursor at the bottom for the other tests
+        writeln!(writer, "\x1b[{BUFFER_HEIGHT};1H").expect("write failed");
+    });
+}
+
+#[test_case]
+fn test_control_characters() {
+    use core::fmt::Write;
+
+    interrupts::without_interrupts(|| {
+        let mut writer = WRITER.lock();
+        write!(writer, "\nabc\rx\ty\x08z").expect("write failed");
+        let bottom = |col| writer.buffer.read(BUFFER_HEIGHT - 1, col);
+        assert_eq!(bottom(0).ascii_character, b'x');
+        // The tab writes spaces over the rest of `abc`
+        assert_eq!(bottom(1).ascii_character, b' ');
+        assert_eq!(bottom(TAB_WIDTH).ascii_character, b'z');
+        assert_eq!(writer.position(), (BUFFER_HEIGHT - 1, TAB_WIDTH + 1));
+    });
+}
+
+#[test_case]
+fn test_backspace_returns_to_the_previous_row() {
+    use core::fmt::Write;
+
+    interrupts::without_interrupts(|| {
+        let mut writer = WRITER.lock();
+        writer.set_position(BUFFER_HEIGHT - 1, 0);
+        writer.write_byte(0x08);
+        assert_eq!(writer.position(), (BUFFER_HEIGHT - 2, BUFFER_WIDTH - 1));
+        writeln!(writer, "\n").expect("write failed");
+        assert_eq!(writer.position(), (BUFFER_HEIGHT - 1, 0));
+    });
+}
+
+#[test_case]
+fn test_unicode_is_translated() {
+    use core::fmt::Write;
+
+    interrupts::without_interrupts(|| {
+        let mut writer = WRITER.lock();
+        write!(writer, "\n╔é€").expect("write failed");
+        let bottom = |col| writer.buffer.read(BUFFER_HEIGHT - 1, col);
+        let text: [u8; 3] = core::array::from_fn(|col| bottom(col).ascii_character);
+        assert_eq!(text, [0xc9, 0x82, cp437::REPLACEMENT]);
+    });
+}
+
+#[test_case]
+fn test_glyphs_of_control_bytes_are_drawn() {
+    use core::fmt::Write;
+
+    interrupts::without_interrupts(|| {
+        let mut writer = WRITER.lock();
+        write!(writer, "\n◘○◙♪").expect("write failed");
+        assert_eq!(writer.position(), (BUFFER_HEIGHT - 1, 4));
+        let bottom = |col| writer.buffer.read(BUFFER_HEIGHT - 1, col);
+        let text: [u8; 4] = core::array::from_fn(|col| bottom(col).ascii_character);
+        assert_eq!(text, [0x08, 0x09, 0x0a, 0x0d]);
+    });
+}
+
+#[test_case]
+fn test_scrollback() {
+    use core::fmt::Write;
+
+    interrupts::without_interrupts(|| {
+        let mut writer = WRITER.lock();
+        writeln!(writer, "\ntest_scrollback marker").expect("write failed");
+        for _ in 0..BUFFER_HEIGHT {
+            writeln!(writer).expect("write failed");
+        }
+        assert!(writer.scrollback_len() >= 2);
+
+        // The marker left the screen two lines ago
+        writer.scroll_up(2);
+        assert_eq!(writer.scroll_offset(), 2);
+        assert_eq!(writer.buffer.read(0, 0).ascii_character, b't');
+
+        writer.scroll_down(1);
+        assert_eq!(writer.scroll_offset(), 1);
+        assert_eq!(writer.buffer.read(0, 0).ascii_character, b' ');
+
+        writer.scroll_up(usize::MAX);
+        assert_eq!(writer.scroll_offset(), writer.scrollback_len());
+
+        // New output shows the live screen again
+        write!(writer, "x").expect("write failed");
+        assert_eq!(writer.scroll_offset(), 0);
+        let bottom = |col| writer.buffer.read(BUFFER_HEIGHT - 1, col);
+        assert_eq!(bottom(0).ascii_character, b'x');
+        assert_eq!(writer.buffer.read(0, 0).ascii_character, b' ');
+        writeln!(writer).expect("write failed");
+    });
+}
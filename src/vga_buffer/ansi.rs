@@ -0,0 +1,268 @@
+/// Maximum number of parameters of a control sequence, the rest is ignored
+pub const MAX_PARAMS: usize = 16;
+
+/// The escape character starting every sequence
+const ESC: char = '\x1b';
+
+/// What the writer has to do after a character was fed to the `Parser`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    /// The character is not part of a sequence
+    Print(char),
+    /// Part of an unfinished sequence, or of one that isn't supported
+    None,
+    /// `CSI n A`, moves the cursor up by at least one row
+    CursorUp(usize),
+    /// `CSI n B`, moves the cursor down by at least one row
+    CursorDown(usize),
+    /// `CSI n C`, moves the cursor right by at least one column
+    CursorForward(usize),
+    /// `CSI n D`, moves the cursor left by at least one column
+    CursorBack(usize),
+    /// `CSI row ; col H`, moves the cursor to the zero based position
+    CursorPosition { row: usize, col: usize },
+    /// `CSI n J`, clears the screen
+    EraseDisplay(Erase),
+    /// `CSI n K`, clears the line of the cursor
+    EraseLine(Erase),
+    /// `CSI n ; ... m`, changes the colors
+    SelectGraphicRendition(Params),
+}
+
+/// The part of the screen or line cleared by `EraseDisplay` and `EraseLine`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Erase {
+    /// From the cursor to the end
+    ToEnd,
+    /// From the start to the cursor, including it
+    ToStart,
+    All,
+}
+
+/// The numeric parameters of a control sequence
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Params {
+    values: [u16; MAX_PARAMS],
+    len: usize,
+}
+
+impl Params {
+    const fn new() -> Self {
+        Self {
+            values: [0; MAX_PARAMS],
+            len: 0,
+        }
+    }
+
+    /// The parameters, an omitted one is zero
+    #[must_use]
+    pub fn as_slice(&self) -> &[u16] {
+        &self.values[..self.len]
+    }
+
+    /// Returns the parameter at `index`, or `default` if it was omitted or zero
+    fn get_or(&self, index: usize, default: u16) -> u16 {
+        match self.as_slice().get(index) {
+            Some(&value) if value != 0 => value,
+            _ => default,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Ground,
+    /// After `ESC`
+    Escape,
+    /// After `ESC [`
+    Csi,
+    /// Inside a sequence with a private marker like `ESC [ ?`, which is ignored
+    IgnoredCsi,
+}
+
+/// A state machine recognizing the VT100 sequences the writer supports
+#[derive(Debug, Clone, Copy)]
+pub struct Parser {
+    state: State,
+    params: Params,
+    /// Whether a digit was seen since the last `;`
+    has_digits: bool,
+}
+
+impl Parser {
+    pub const fn new() -> Self {
+        Self {
+            state: State::Ground,
+            params: Params::new(),
+            has_digits: false,
+        }
+    }
+
+    /// Feeds the next character of the output to the parser
+    pub fn advance(&mut self, c: char) -> Action {
+        match self.state {
+            State::Ground if c == ESC => {
+                self.state = State::Escape;
+                Action::None
+            }
+            State::Ground => Action::Print(c),
+            State::Escape if c == '[' => {
+                self.state = State::Csi;
+                self.params = Params::new();
+                self.has_digits = false;
+                Action::None
+            }
+            // Other escapes aren't supported, a new one restarts
+            State::Escape => {
+                self.state = if c == ESC {
+                    State::Escape
+                } else {
+                    State::Ground
+                };
+                Action::None
+            }
+            State::Csi => self.advance_csi(c),
+            State::IgnoredCsi => {
+                if is_final(c) {
+                    self.state = State::Ground;
+                }
+                Action::None
+            }
+        }
+    }
+
+    fn advance_csi(&mut self, c: char) -> Action {
+        match c {
+            '0'..='9' => {
+                if !self.has_digits {
+                    self.push_param();
+                    self.has_digits = true;
+                }
+                if let Some(value) = self.params.values.get_mut(self.params.len.wrapping_sub(1)) {
+                    let digit = c as u16 - u16::from(b'0');
+                    *value = value.saturating_mul(10).saturating_add(digit);
+                }
+                Action::None
+            }
+            ';' => {
+                if !self.has_digits {
+                    self.push_param();
+                }
+                self.has_digits = false;
+                Action::None
+            }
+            '<'..='?' => {
+                self.state = State::IgnoredCsi;
+                Action::None
+            }
+            c if is_final(c) => {
+                self.state = State::Ground;
+                self.dispatch(c)
+            }
+            // Intermediate bytes and stray characters end the sequence
+            _ => {
+                self.state = State::Ground;
+                Action::None
+            }
+        }
+    }
+
+    /// Starts a new parameter, extra ones beyond `MAX_PARAMS` are dropped
+    fn push_param(&mut self) {
+        if self.params.len < MAX_PARAMS {
+            self.params.values[self.params.len] = 0;
+            self.params.len += 1;
+        }
+    }
+
+    fn dispatch(&self, c: char) -> Action {
+        let params = &self.params;
+        let count = || usize::from(params.get_or(0, 1));
+        match c {
+            'A' => Action::CursorUp(count()),
+            'B' => Action::CursorDown(count()),
+            'C' => Action::CursorForward(count()),
+            'D' => Action::CursorBack(count()),
+            'H' | 'f' => Action::CursorPosition {
+                row: usize::from(params.get_or(0, 1)) - 1,
+                col: usize::from(params.get_or(1, 1)) - 1,
+            },
+            'J' => erase(params).map_or(Action::None, Action::EraseDisplay),
+            'K' => erase(params).map_or(Action::None, Action::EraseLine),
+            'm' => Action::SelectGraphicRendition(*params),
+            _ => Action::None,
+        }
+    }
+}
+
+const fn is_final(c: char) -> bool {
+    matches!(c, '@'..='~')
+}
+
+fn erase(params: &Params) -> Option<Erase> {
+    match params.as_slice().first().copied().unwrap_or(0) {
+        0 => Some(Erase::ToEnd),
+        1 => Some(Erase::ToStart),
+        // 3 also clears the scrollback of a terminal
+        2 | 3 => Some(Erase::All),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+fn feed(parser: &mut Parser, s: &str) -> Action {
+    s.chars()
+        .map(|c| parser.advance(c))
+        .last()
+        .unwrap_or(Action::None)
+}
+
+#[test_case]
+fn test_plain_text_is_printed() {
+    let mut parser = Parser::new();
+    assert_eq!(parser.advance('a'), Action::Print('a'));
+    assert_eq!(parser.advance('\n'), Action::Print('\n'));
+}
+
+#[test_case]
+fn test_cursor_sequences() {
+    let mut parser = Parser::new();
+    assert_eq!(feed(&mut parser, "\x1b[A"), Action::CursorUp(1));
+    assert_eq!(feed(&mut parser, "\x1b[12D"), Action::CursorBack(12));
+    assert_eq!(
+        feed(&mut parser, "\x1b[5;10H"),
+        Action::CursorPosition { row: 4, col: 9 }
+    );
+    assert_eq!(
+        feed(&mut parser, "\x1b[;3H"),
+        Action::CursorPosition { row: 0, col: 2 }
+    );
+    assert_eq!(
+        feed(&mut parser, "\x1b[2J"),
+        Action::EraseDisplay(Erase::All)
+    );
+    assert_eq!(feed(&mut parser, "\x1b[K"), Action::EraseLine(Erase::ToEnd));
+}
+
+#[test_case]
+fn test_graphic_rendition_params() {
+    let mut parser = Parser::new();
+    let Action::SelectGraphicRendition(params) = feed(&mut parser, "\x1b[1;;31m") else {
+        panic!("not an SGR sequence");
+    };
+    assert_eq!(params.as_slice(), &[1, 0, 31]);
+
+    let Action::SelectGraphicRendition(params) = feed(&mut parser, "\x1b[m") else {
+        panic!("not an SGR sequence");
+    };
+    assert!(params.as_slice().is_empty());
+}
+
+#[test_case]
+fn test_unsupported_sequences_are_swallowed() {
+    let mut parser = Parser::new();
+    assert_eq!(feed(&mut parser, "\x1b[?25l"), Action::None);
+    assert_eq!(feed(&mut parser, "\x1b[5n"), Action::None);
+    assert_eq!(feed(&mut parser, "\x1bc"), Action::None);
+    assert_eq!(parser.advance('x'), Action::Print('x'));
+}
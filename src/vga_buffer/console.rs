@@ -0,0 +1,214 @@
+use super::{scrollback::Scrollback, ScreenChar, TextBuffer, Writer, SCROLLBACK_LINES, WRITER};
+use super::{ScreenPointer, SCREEN};
+use super::{BUFFER_HEIGHT, BUFFER_WIDTH, MAX_COLUMNS, MAX_ROWS};
+use core::fmt;
+use core::ptr::addr_of_mut;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use spin::Mutex;
+use x86_64::instructions::interrupts::without_interrupts;
+
+/// Number of virtual consoles, shown with Alt+F1 to Alt+F6
+pub const CONSOLES: usize = 6;
+/// The console of `WRITER`, which the print macros and the logger write to
+pub const KERNEL_CONSOLE: usize = 0;
+
+/// The screens of the consoles while they aren't shown
+static mut SCREENS: [Screen; CONSOLES] = {
+    const EMPTY: Screen = Screen::new();
+    [EMPTY; CONSOLES]
+};
+/// The histories of the consoles other than the kernel one
+static mut SCROLLBACKS: [Scrollback<SCROLLBACK_LINES>; CONSOLES - 1] = {
+    const EMPTY: Scrollback<SCROLLBACK_LINES> = Scrollback::new();
+    [EMPTY; CONSOLES - 1]
+};
+/// The console on the screen
+static ACTIVE: AtomicUsize = AtomicUsize::new(KERNEL_CONSOLE);
+
+lazy_static::lazy_static! {
+    /// The writers of the consoles other than the kernel one, they start off screen
+    static ref OTHERS: [Mutex<Writer>; CONSOLES - 1] = core::array::from_fn(|index| {
+        // Every writer is created once and is the only one using its index
+        let scrollback = unsafe { &mut *addr_of_mut!(SCROLLBACKS[index]) };
+        let mut writer = Writer::new(Some(scrollback));
+        let screen = unsafe { screen(index + 1) };
+        // Like the screen of the console that is shown, once the writer moves there
+        let (rows, cols) = writer.size();
+        screen.rows = rows;
+        screen.cols = cols;
+        writer.buffer = screen;
+        for row in 0..rows {
+            writer.clean_row(row);
+        }
+        Mutex::new(writer)
+    });
+}
+
+/// The screen of a console that isn't shown, in memory
+struct Screen {
+    chars: [[ScreenChar; MAX_COLUMNS]; MAX_ROWS],
+    rows: usize,
+    cols: usize,
+}
+
+impl Screen {
+    const fn new() -> Self {
+        Self {
+            chars: [[ScreenChar::ZERO; MAX_COLUMNS]; MAX_ROWS],
+            rows: BUFFER_HEIGHT,
+            cols: BUFFER_WIDTH,
+        }
+    }
+}
+
+impl TextBuffer for Screen {
+    fn size(&self) -> (usize, usize) {
+        (self.rows, self.cols)
+    }
+
+    fn read(&self, row: usize, col: usize) -> ScreenChar {
+        self.chars[row][col]
+    }
+
+    fn write(&mut self, row: usize, col: usize, screen_char: ScreenChar) {
+        self.chars[row][col] = screen_char;
+    }
+}
+
+/// Returns the off-screen copy of the screen of `console`
+///
+/// # Safety
+///
+/// Only the writer of the console may use it, and only while the console isn't shown.
+unsafe fn screen(console: usize) -> &'static mut Screen {
+    unsafe { &mut *addr_of_mut!(SCREENS[console]) }
+}
+
+/// Returns the writer of `console`, `None` if there is no such console
+#[must_use]
+pub fn writer(console: usize) -> Option<&'static Mutex<Writer>> {
+    match console {
+        KERNEL_CONSOLE => Some(&WRITER),
+        console => OTHERS.get(console - 1),
+    }
+}
+
+/// Returns the console on the screen
+#[must_use]
+pub fn active() -> usize {
+    ACTIVE.load(Ordering::SeqCst)
+}
+
+/// Shows `console` on the screen, the others keep their output off screen
+///
+/// Does nothing if there is no such console.
+pub fn switch(console: usize) {
+    let Some(new) = writer(console) else {
+        return;
+    };
+
+    without_interrupts(|| {
+        let active = active();
+        if console == active {
+            return;
+        }
+        let Some(old) = writer(active) else {
+            return;
+        };
+        let (mut old, mut new) = (old.lock(), new.lock());
+
+        // The old console continues off screen, in a copy as big as the screen
+        let off_screen = unsafe { screen(active) };
+        (off_screen.rows, off_screen.cols) = old.size();
+        let shown = old.replace_buffer(off_screen);
+        new.replace_buffer(shown);
+
+        ACTIVE.store(console, Ordering::SeqCst);
+    });
+}
+
+/// Moves the console on the screen to another `screen`, like the framebuffer console
+///
+/// Returns the screen it used before.
+pub(crate) fn replace_screen(screen: &'static mut dyn TextBuffer) -> &'static mut dyn TextBuffer {
+    without_interrupts(|| {
+        let active = writer(active()).unwrap_or(&WRITER);
+        let mut active = active.lock();
+        *SCREEN.lock() = Some(ScreenPointer(&mut *screen));
+        active.replace_buffer(screen)
+    })
+}
+
+/// Draws the console on the screen again, like after graphics were drawn over it
+pub(crate) fn redraw_screen() {
+    without_interrupts(|| {
+        let active = writer(active()).unwrap_or(&WRITER);
+        active.lock().buffer.redraw();
+    });
+}
+
+/// Prints to `console` without keeping a copy in the kernel log
+///
+/// Does nothing if there is no such console.
+pub fn write_fmt(console: usize, args: fmt::Arguments) {
+    use core::fmt::Write;
+
+    if let Some(writer) = writer(console) {
+        without_interrupts(|| {
+            let _ = writer.lock().write_fmt(args);
+        });
+    }
+}
+
+/// Releases the writers of the other consoles no matter who holds them
+///
+/// # Safety
+///
+/// The holders must never use the writers again, like the code interrupted by a panic.
+pub(super) unsafe fn force_unlock() {
+    for writer in OTHERS.iter() {
+        unsafe { writer.force_unlock() };
+    }
+}
+
+#[test_case]
+fn test_consoles_keep_their_screens() {
+    let read = |console: usize| {
+        let writer = writer(console).expect("no such console").lock();
+        let (rows, _) = writer.size();
+        writer.buffer.read(rows - 1, 0).byte()
+    };
+
+    write_fmt(1, format_args!("\n1"));
+    write_fmt(KERNEL_CONSOLE, format_args!("\n0"));
+    assert_eq!(without_interrupts(|| read(1)), b'1');
+
+    switch(1);
+    assert_eq!(active(), 1);
+    write_fmt(KERNEL_CONSOLE, format_args!("\nx"));
+    without_interrupts(|| {
+        assert!(writer(1)
+            .expect("no such console")
+            .lock()
+            .buffer
+            .is_screen());
+        assert!(!WRITER.lock().buffer.is_screen());
+        assert_eq!(read(1), b'1');
+        assert_eq!(read(KERNEL_CONSOLE), b'x');
+    });
+
+    switch(KERNEL_CONSOLE);
+    without_interrupts(|| {
+        assert!(WRITER.lock().buffer.is_screen());
+        assert_eq!(read(KERNEL_CONSOLE), b'x');
+        assert_eq!(read(1), b'1');
+    });
+    crate::println!();
+}
+
+#[test_case]
+fn test_switch_to_missing_console() {
+    switch(CONSOLES);
+    assert_eq!(active(), KERNEL_CONSOLE);
+    assert!(writer(CONSOLES).is_none());
+}
@@ -0,0 +1,93 @@
+/// Byte the VGA font shows as `■`, used for the characters it doesn't have
+pub const REPLACEMENT: u8 = 0xfe;
+
+/// The glyphs of the bytes 0x01 to 0x1f, which are control characters in ASCII
+const LOW: [char; 31] = [
+    '☺', '☻', '♥', '♦', '♣', '♠', '•', '◘', '○', '◙', '♂', '♀', '♪', '♫', '☼', '►', '◄', '↕', '‼',
+    '¶', '§', '▬', '↨', '↑', '↓', '→', '←', '∟', '↔', '▲', '▼',
+];
+
+/// The glyphs of the bytes 0x80 to 0xff
+const HIGH: [char; 128] = [
+    'Ç', 'ü', 'é', 'â', 'ä', 'à', 'å', 'ç', 'ê', 'ë', 'è', 'ï', 'î', 'ì', 'Ä', 'Å', //
+    'É', 'æ', 'Æ', 'ô', 'ö', 'ò', 'û', 'ù', 'ÿ', 'Ö', 'Ü', '¢', '£', '¥', '₧', 'ƒ', //
+    'á', 'í', 'ó', 'ú', 'ñ', 'Ñ', 'ª', 'º', '¿', '⌐', '¬', '½', '¼', '¡', '«', '»', //
+    '░', '▒', '▓', '│', '┤', '╡', '╢', '╖', '╕', '╣', '║', '╗', '╝', '╜', '╛', '┐', //
+    '└', '┴', '┬', '├', '─', '┼', '╞', '╟', '╚', '╔', '╩', '╦', '╠', '═', '╬', '╧', //
+    '╨', '╤', '╥', '╙', '╘', '╒', '╓', '╫', '╪', '┘', '┌', '█', '▄', '▌', '▐', '▀', //
+    'α', 'ß', 'Γ', 'π', 'Σ', 'σ', 'µ', 'τ', 'Φ', 'Θ', 'Ω', 'δ', '∞', 'φ', 'ε', '∩', //
+    '≡', '±', '≥', '≤', '⌠', '⌡', '÷', '≈', '°', '∙', '·', '√', 'ⁿ', '²', '■', '\u{a0}',
+];
+
+/// Characters that look the same as a glyph of the font, but have another code point
+const ALIASES: [(char, u8); 5] = [
+    // Greek small beta, drawn like the German sharp s
+    ('\u{3b2}', 0xe1),
+    // Greek small mu, the table has the micro sign
+    ('\u{3bc}', 0xe6),
+    // Ohm sign, the table has the Greek capital omega
+    ('\u{2126}', 0xea),
+    // Element of, drawn like the small epsilon
+    ('\u{2208}', 0xee),
+    // The glyph of DEL, which `encode` doesn't take for a printable ASCII character
+    ('⌂', 0x7f),
+];
+
+/// Returns the byte of `c` in code page 437, the character set of the VGA font
+#[must_use]
+pub fn encode(c: char) -> Option<u8> {
+    if (' '..='~').contains(&c) {
+        return Some(c as u8);
+    }
+
+    let position = |table: &[char]| table.iter().position(|&glyph| glyph == c);
+    if let Some(index) = position(&HIGH) {
+        return Some(0x80 + index as u8);
+    }
+    if let Some(index) = position(&LOW) {
+        return Some(0x01 + index as u8);
+    }
+    ALIASES
+        .iter()
+        .find(|&&(alias, _)| alias == c)
+        .map(|&(_, byte)| byte)
+}
+
+/// Returns the character the VGA font shows for `byte`, the opposite of `encode`
+///
+/// The empty glyph of byte zero is a NUL.
+#[must_use]
+pub fn decode(byte: u8) -> char {
+    match byte {
+        0x01..=0x1f => LOW[usize::from(byte) - 0x01],
+        0x7f => '⌂',
+        0x80..=0xff => HIGH[usize::from(byte) - 0x80],
+        _ => char::from(byte),
+    }
+}
+
+#[test_case]
+fn test_encode_ascii() {
+    assert_eq!(encode('A'), Some(b'A'));
+    assert_eq!(encode('~'), Some(b'~'));
+    // Control characters aren't glyphs
+    assert_eq!(encode('\x07'), None);
+}
+
+#[test_case]
+fn test_encode_non_ascii() {
+    assert_eq!(encode('é'), Some(0x82));
+    assert_eq!(encode('═'), Some(0xcd));
+    assert_eq!(encode('☺'), Some(0x01));
+    assert_eq!(encode('▼'), Some(0x1f));
+    assert_eq!(encode('\u{a0}'), Some(0xff));
+    assert_eq!(encode('μ'), Some(0xe6));
+    assert_eq!(encode('€'), None);
+}
+
+#[test_case]
+fn test_decode_is_the_opposite_of_encode() {
+    for byte in 1..=0xff {
+        assert_eq!(encode(decode(byte)), Some(byte));
+    }
+}
@@ -0,0 +1,103 @@
+use super::{ScreenChar, TextBuffer, MAX_COLUMNS, MAX_ROWS};
+
+/// A row of the screen, as wide as the widest screen
+type Line = [ScreenChar; MAX_COLUMNS];
+
+/// The lines that scrolled off the top of the screen, up to `DEPTH` of them
+pub(super) struct Scrollback<const DEPTH: usize> {
+    lines: [Line; DEPTH],
+    /// Index of the oldest line in `lines`
+    start: usize,
+    len: usize,
+    /// Number of lines the view is scrolled up, zero shows the live screen
+    offset: usize,
+    /// The live screen, saved while the view is scrolled up
+    live: [Line; MAX_ROWS],
+}
+
+impl<const DEPTH: usize> Scrollback<DEPTH> {
+    /// An empty history, all zeroes so it doesn't take space in the kernel image
+    pub const fn new() -> Self {
+        Self {
+            lines: [[ScreenChar::ZERO; MAX_COLUMNS]; DEPTH],
+            start: 0,
+            len: 0,
+            offset: 0,
+            live: [[ScreenChar::ZERO; MAX_COLUMNS]; MAX_ROWS],
+        }
+    }
+
+    /// Number of lines in the history
+    pub const fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Number of lines the view is scrolled up
+    pub const fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// Adds `row` of `buffer` as it leaves the screen, dropping the oldest line when full
+    pub fn push(&mut self, buffer: &dyn TextBuffer, row: usize) {
+        if DEPTH == 0 {
+            return;
+        }
+        let index = if self.len < DEPTH {
+            self.len += 1;
+            (self.start + self.len - 1) % DEPTH
+        } else {
+            let index = self.start;
+            self.start = (self.start + 1) % DEPTH;
+            index
+        };
+        read_line(buffer, row, &mut self.lines[index]);
+    }
+
+    /// Returns the line `index` lines after the oldest one
+    fn line(&self, index: usize) -> &Line {
+        &self.lines[(self.start + index) % DEPTH]
+    }
+
+    /// Shows the screen scrolled up by `offset` lines, at most to the oldest line
+    ///
+    /// The live screen is saved when the view leaves it and restored when it returns,
+    /// so nothing may write to `buffer` in the meantime.
+    pub fn scroll_to(&mut self, buffer: &mut dyn TextBuffer, offset: usize) {
+        let offset = offset.min(self.len);
+        if offset == self.offset {
+            return;
+        }
+        let (rows, cols) = buffer.size();
+        if self.offset == 0 {
+            for (row, line) in self.live[..rows].iter_mut().enumerate() {
+                read_line(buffer, row, line);
+            }
+        }
+        self.offset = offset;
+
+        // The history followed by the live screen is one long list of lines
+        for row in 0..rows {
+            let index = self.len - offset + row;
+            let line = if index < self.len {
+                self.line(index)
+            } else {
+                &self.live[index - self.len]
+            };
+            for (col, &screen_char) in line[..cols].iter().enumerate() {
+                buffer.write(row, col, screen_char);
+            }
+        }
+    }
+}
+
+/// Copies `row` of `buffer` into `line`, the columns beyond the screen are left empty
+fn read_line(buffer: &dyn TextBuffer, row: usize, line: &mut Line) {
+    let (_, cols) = buffer.size();
+    for (col, screen_char) in line.iter_mut().enumerate() {
+        *screen_char = if col < cols {
+            buffer.read(row, col)
+        } else {
+            ScreenChar::ZERO
+        };
+    }
+}
@@ -0,0 +1,68 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(rudos::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use bootloader::{entry_point, BootInfo};
+use rudos::acpi;
+use rudos::memory::{self, BitmapFrameAllocator};
+use x86_64::VirtAddr;
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    rudos::init();
+
+    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    let mut mapper = unsafe { memory::init(phys_mem_offset) };
+    let mut frame_allocator =
+        unsafe { BitmapFrameAllocator::init(&boot_info.memory_map, phys_mem_offset) };
+    memory::heap::init(&mut mapper, &mut frame_allocator).expect("Heap initialization failed");
+    unsafe { acpi::init(phys_mem_offset) }.expect("Reading the ACPI tables failed");
+
+    test_main();
+    rudos::hlt_loop();
+}
+
+#[panic_handler]
+fn panic(info: &core::panic::PanicInfo) -> ! {
+    rudos::test_panic_handler(info)
+}
+
+fn tables() -> &'static acpi::AcpiTables {
+    acpi::tables().expect("ACPI tables are not initialized")
+}
+
+#[test_case]
+fn madt_lists_cpus_and_io_apic() {
+    let madt = tables().madt.as_ref().expect("MADT not found");
+    assert!(madt.processors.iter().any(|cpu| cpu.usable));
+    assert!(!madt.io_apics.is_empty());
+    assert_ne!(madt.local_apic_address, 0);
+}
+
+#[test_case]
+fn timer_is_overridden() {
+    // QEMU connects the PIT to the second pin of the IOAPIC, like real hardware
+    let madt = tables().madt.as_ref().expect("MADT not found");
+    let timer = madt
+        .interrupt_overrides
+        .iter()
+        .find(|o| o.source == 0)
+        .expect("Timer override not found");
+    assert_eq!(timer.gsi, 2);
+}
+
+#[test_case]
+fn fadt_has_power_management_ports() {
+    let fadt = tables().fadt.expect("FADT not found");
+    assert_ne!(fadt.pm1a_control_block, 0);
+    assert_ne!(fadt.pm_timer_block, 0);
+}
+
+#[test_case]
+fn hpet_is_present() {
+    let hpet = tables().hpet.expect("HPET table not found");
+    assert_ne!(hpet.address, 0);
+}
@@ -0,0 +1,92 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(rudos::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use bootloader::{entry_point, BootInfo};
+use core::sync::atomic::{AtomicUsize, Ordering};
+use rudos::interrupts::{self, apic, InterruptController, TIMER_IRQ};
+use rudos::memory::{self, BitmapFrameAllocator};
+use rudos::time::{self, ClockSource, Duration, Instant};
+use x86_64::VirtAddr;
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    rudos::init();
+
+    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    let mut mapper = unsafe { memory::init(phys_mem_offset) };
+    let mut frame_allocator =
+        unsafe { BitmapFrameAllocator::init(&boot_info.memory_map, phys_mem_offset) };
+    // The clock has to keep running while the timer line is masked
+    let clock = time::init_high_resolution(&mut mapper, &mut frame_allocator);
+    assert_ne!(clock, ClockSource::Pit);
+    let controller =
+        interrupts::select_controller(InterruptController::Apic, &mut mapper, &mut frame_allocator)
+            .expect("Switching the interrupt controller failed");
+    // QEMU always emulates an APIC
+    assert_eq!(controller, InterruptController::Apic);
+
+    test_main();
+    rudos::hlt_loop();
+}
+
+#[panic_handler]
+fn panic(info: &core::panic::PanicInfo) -> ! {
+    rudos::test_panic_handler(info)
+}
+
+static TICKS: AtomicUsize = AtomicUsize::new(0);
+
+fn count_tick() {
+    TICKS.fetch_add(1, Ordering::SeqCst);
+}
+
+#[test_case]
+fn apic_is_active() {
+    assert_eq!(interrupts::active_controller(), InterruptController::Apic);
+}
+
+#[test_case]
+fn cascade_line_leaves_the_timer_pin_alone() {
+    // The PIT is wired to pin 2, where the cascade line would be identity mapped
+    assert_eq!(
+        apic::redirection_vector(2),
+        Some(interrupts::irq_vector(TIMER_IRQ))
+    );
+}
+
+#[test_case]
+fn timer_is_routed_through_io_apic() {
+    interrupts::register_irq(TIMER_IRQ, count_tick).expect("Registering failed");
+
+    // Every tick needs an EOI on the local APIC, otherwise the second one never comes
+    while TICKS.load(Ordering::SeqCst) < 2 {
+        x86_64::instructions::hlt();
+    }
+
+    interrupts::unregister_irq(TIMER_IRQ, count_tick).expect("Unregistering failed");
+}
+
+#[test_case]
+fn masked_line_stays_quiet() {
+    interrupts::register_irq(TIMER_IRQ, count_tick).expect("Registering failed");
+    interrupts::mask_irq(TIMER_IRQ);
+
+    let ticks = TICKS.load(Ordering::SeqCst);
+    // Ten periods of the 1 kHz timer
+    let deadline = Instant::now() + Duration::from_millis(10);
+    while Instant::now() < deadline {
+        core::hint::spin_loop();
+    }
+    assert_eq!(TICKS.load(Ordering::SeqCst), ticks);
+
+    // Only the mask kept it quiet, the ticks come back without it
+    interrupts::unmask_irq(TIMER_IRQ);
+    while TICKS.load(Ordering::SeqCst) == ticks {
+        x86_64::instructions::hlt();
+    }
+    interrupts::unregister_irq(TIMER_IRQ, count_tick).expect("Unregistering failed");
+}
@@ -10,7 +10,7 @@ use rudos::println;
 pub extern "C" fn _start() -> ! {
     test_main();
 
-    loop {}
+    rudos::hlt_loop();
 }
 
 #[panic_handler]
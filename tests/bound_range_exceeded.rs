@@ -0,0 +1,11 @@
+#![no_std]
+#![no_main]
+
+use core::arch::asm;
+
+rudos::exception_test!("BOUND RANGE EXCEEDED", trigger);
+
+/// `bound` doesn't exist in long mode, so we raise the vector directly
+fn trigger() {
+    unsafe { asm!("int 5") };
+}
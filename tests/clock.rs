@@ -0,0 +1,82 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(rudos::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use bootloader::{entry_point, BootInfo};
+use rudos::memory::{self, BitmapFrameAllocator};
+use rudos::time::{self, hpet, tsc, ClockSource, Duration, Instant};
+use x86_64::VirtAddr;
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    rudos::init();
+
+    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    let mut mapper = unsafe { memory::init(phys_mem_offset) };
+    let mut frame_allocator =
+        unsafe { BitmapFrameAllocator::init(&boot_info.memory_map, phys_mem_offset) };
+    memory::heap::init(&mut mapper, &mut frame_allocator).expect("Heap initialization failed");
+    unsafe { rudos::acpi::init(phys_mem_offset) }.expect("Reading the ACPI tables failed");
+    time::init_high_resolution(&mut mapper, &mut frame_allocator);
+
+    test_main();
+    rudos::hlt_loop();
+}
+
+#[panic_handler]
+fn panic(info: &core::panic::PanicInfo) -> ! {
+    rudos::test_panic_handler(info)
+}
+
+#[test_case]
+fn hpet_is_found() {
+    // QEMU always emulates an HPET with a 100 MHz counter
+    assert!(hpet::is_available());
+    assert_eq!(hpet::frequency_hz(), Some(100_000_000));
+}
+
+#[test_case]
+fn high_resolution_clock_is_used() {
+    assert_ne!(time::clock_source(), ClockSource::Pit);
+    assert!(tsc::frequency_hz().is_some());
+}
+
+#[test_case]
+fn clock_resolves_below_a_tick() {
+    let ticks = time::ticks();
+    let start = Instant::now();
+    let mut now = start;
+    while now == start {
+        now = Instant::now();
+    }
+    // Both reads happened within the same tick, so the clock is finer than the PIT
+    if time::ticks() == ticks {
+        assert!(now - start < Duration::from_millis(1));
+    }
+}
+
+#[test_case]
+fn clock_is_monotonic() {
+    let mut previous = Instant::now();
+    for _ in 0..10_000 {
+        let now = Instant::now();
+        assert!(now >= previous);
+        previous = now;
+    }
+}
+
+#[test_case]
+fn clock_agrees_with_the_ticks() {
+    let ticks = time::ticks();
+    let start = Instant::now();
+    time::sleep(Duration::from_millis(50));
+    let elapsed_ticks = time::ticks() - ticks;
+    let elapsed = start.elapsed();
+
+    let tick = Duration::from_secs(1) / time::frequency_hz();
+    assert!(elapsed >= Duration::from_millis(50));
+    assert!(elapsed <= tick * (elapsed_ticks as u32 + 2));
+}
@@ -0,0 +1,16 @@
+#![no_std]
+#![no_main]
+
+use core::arch::asm;
+
+rudos::exception_test!("DEVICE NOT AVAILABLE", trigger);
+
+/// Executes an x87 instruction after a task switch was flagged
+fn trigger() {
+    use x86_64::registers::control::{Cr0, Cr0Flags};
+
+    unsafe {
+        Cr0::update(|flags| flags.insert(Cr0Flags::TASK_SWITCHED));
+        asm!("fninit");
+    }
+}
@@ -0,0 +1,13 @@
+#![no_std]
+#![no_main]
+
+use core::arch::asm;
+
+rudos::exception_test!("DIVIDE ERROR", trigger);
+
+/// Divides by zero with the `div` instruction
+fn trigger() {
+    unsafe {
+        asm!("div {0}", in(reg) 0u64, inout("rax") 1u64 => _, inout("rdx") 0u64 => _);
+    }
+}
@@ -0,0 +1,11 @@
+#![no_std]
+#![no_main]
+
+rudos::exception_test!("DOUBLE FAULT", trigger);
+
+/// Overflows the stack, the page fault can't be pushed onto it anymore
+#[allow(unconditional_recursion)]
+fn trigger() {
+    trigger();
+    volatile::Volatile::new(0).read(); // Prevents tail recursion optimisation
+}
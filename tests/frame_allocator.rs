@@ -0,0 +1,98 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(rudos::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use bootloader::{entry_point, BootInfo};
+use rudos::memory::BitmapFrameAllocator;
+use spin::Mutex;
+use x86_64::structures::paging::{
+    FrameAllocator, FrameDeallocator, PageSize, PhysFrame, Size2MiB, Size4KiB,
+};
+use x86_64::VirtAddr;
+
+static FRAME_ALLOCATOR: Mutex<Option<BitmapFrameAllocator>> = Mutex::new(None);
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    rudos::init();
+
+    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    let frame_allocator =
+        unsafe { BitmapFrameAllocator::init(&boot_info.memory_map, phys_mem_offset) };
+    *FRAME_ALLOCATOR.lock() = Some(frame_allocator);
+
+    test_main();
+    rudos::hlt_loop();
+}
+
+#[panic_handler]
+fn panic(info: &core::panic::PanicInfo) -> ! {
+    rudos::test_panic_handler(info)
+}
+
+/// Runs `f` with the allocator that was initialized in `main`
+fn with_allocator(f: impl FnOnce(&mut BitmapFrameAllocator)) {
+    let mut allocator = FRAME_ALLOCATOR.lock();
+    f(allocator
+        .as_mut()
+        .expect("Frame allocator is not initialized"));
+}
+
+#[test_case]
+fn counts_add_up() {
+    with_allocator(|allocator| {
+        assert!(allocator.free_frames() > 0);
+        assert_eq!(
+            allocator.free_frames() + allocator.used_frames(),
+            allocator.usable_frames()
+        );
+    });
+}
+
+#[test_case]
+fn freed_frame_is_reused() {
+    with_allocator(|allocator| {
+        let free_before = allocator.free_frames();
+
+        let frame: PhysFrame<Size4KiB> = allocator.allocate_frame().expect("Out of frames");
+        assert_eq!(allocator.free_frames(), free_before - 1);
+
+        unsafe { allocator.deallocate_frame(frame) };
+        assert_eq!(allocator.free_frames(), free_before);
+
+        let again: PhysFrame<Size4KiB> = allocator.allocate_frame().expect("Out of frames");
+        assert_eq!(frame, again);
+        unsafe { allocator.deallocate_frame(again) };
+    });
+}
+
+#[test_case]
+fn frames_are_unique() {
+    with_allocator(|allocator| {
+        let first: PhysFrame<Size4KiB> = allocator.allocate_frame().expect("Out of frames");
+        let second: PhysFrame<Size4KiB> = allocator.allocate_frame().expect("Out of frames");
+        assert_ne!(first, second);
+
+        unsafe {
+            allocator.deallocate_frame(first);
+            allocator.deallocate_frame(second);
+        }
+    });
+}
+
+#[test_case]
+fn huge_frame_allocation() {
+    with_allocator(|allocator| {
+        let free_before = allocator.free_frames();
+
+        let frame: PhysFrame<Size2MiB> = allocator.allocate_frame().expect("Out of 2MiB frames");
+        assert_eq!(frame.start_address().as_u64() % Size2MiB::SIZE, 0);
+        assert_eq!(allocator.free_frames(), free_before - 512);
+
+        unsafe { allocator.deallocate_frame(frame) };
+        assert_eq!(allocator.free_frames(), free_before);
+    });
+}
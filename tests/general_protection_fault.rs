@@ -0,0 +1,9 @@
+#![no_std]
+#![no_main]
+
+rudos::exception_test!("GENERAL PROTECTION FAULT", trigger);
+
+/// Reads from a non-canonical address
+fn trigger() {
+    let _ = unsafe { core::ptr::read_volatile(0x8000_0000_0000_0000 as *const u64) };
+}
@@ -0,0 +1,91 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(rudos::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use alloc::{boxed::Box, vec::Vec};
+use bootloader::{entry_point, BootInfo};
+use rudos::memory::{self, heap::HEAP_SIZE};
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    use rudos::memory::BitmapFrameAllocator;
+    use x86_64::VirtAddr;
+
+    rudos::init();
+
+    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    let mut mapper = unsafe { memory::init(phys_mem_offset) };
+    let mut frame_allocator =
+        unsafe { BitmapFrameAllocator::init(&boot_info.memory_map, phys_mem_offset) };
+    memory::heap::init(&mut mapper, &mut frame_allocator).expect("Heap initialization failed");
+
+    test_main();
+    rudos::hlt_loop();
+}
+
+#[panic_handler]
+fn panic(info: &core::panic::PanicInfo) -> ! {
+    rudos::test_panic_handler(info)
+}
+
+#[test_case]
+fn simple_allocation() {
+    let heap_value_1 = Box::new(41);
+    let heap_value_2 = Box::new(13);
+    assert_eq!(*heap_value_1, 41);
+    assert_eq!(*heap_value_2, 13);
+}
+
+#[test_case]
+fn large_vec() {
+    let n = 1000;
+    let mut vec = Vec::new();
+    for i in 0..n {
+        vec.push(i);
+    }
+    assert_eq!(vec.iter().sum::<u64>(), (n - 1) * n / 2);
+}
+
+#[test_case]
+fn many_boxes() {
+    // Would run out of memory if the freed boxes weren't reused
+    for i in 0..HEAP_SIZE {
+        let x = Box::new(i);
+        assert_eq!(*x, i);
+    }
+}
+
+#[test_case]
+fn many_boxes_long_lived() {
+    let long_lived = Box::new(1);
+    for i in 0..HEAP_SIZE {
+        let x = Box::new(i);
+        assert_eq!(*x, i);
+    }
+    assert_eq!(*long_lived, 1);
+}
+
+#[test_case]
+fn reuse_after_free() {
+    let first = Box::new([0u8; 512]);
+    let first_addr = &*first as *const [u8; 512] as usize;
+    drop(first);
+
+    let second = Box::new([1u8; 512]);
+    let second_addr = &*second as *const [u8; 512] as usize;
+    assert_eq!(first_addr, second_addr);
+}
+
+#[test_case]
+fn used_bytes_are_counted() {
+    let used = memory::heap::used();
+    let vec = Vec::<u8>::with_capacity(100);
+    assert_eq!(memory::heap::used(), used + 100);
+    drop(vec);
+    assert_eq!(memory::heap::used(), used);
+}
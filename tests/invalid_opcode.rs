@@ -0,0 +1,11 @@
+#![no_std]
+#![no_main]
+
+use core::arch::asm;
+
+rudos::exception_test!("INVALID OPCODE", trigger);
+
+/// Executes the undefined instruction
+fn trigger() {
+    unsafe { asm!("ud2") };
+}
@@ -0,0 +1,14 @@
+#![no_std]
+#![no_main]
+
+use core::arch::asm;
+
+rudos::exception_test!("INVALID TSS", trigger);
+
+/// Only task switches check the TSS, which long mode doesn't have, so we raise the
+/// vector directly
+///
+/// `int` pushes no error code, so the reported one is garbage.
+fn trigger() {
+    unsafe { asm!("int 10") };
+}
@@ -0,0 +1,11 @@
+#![no_std]
+#![no_main]
+
+use core::arch::asm;
+
+rudos::exception_test!("MACHINE CHECK", trigger);
+
+/// Machine checks come from the hardware, so we raise the vector directly
+fn trigger() {
+    unsafe { asm!("int 18") };
+}
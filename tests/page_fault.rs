@@ -0,0 +1,9 @@
+#![no_std]
+#![no_main]
+
+rudos::exception_test!("PAGE FAULT", trigger);
+
+/// Writes to an unmapped address
+fn trigger() {
+    unsafe { core::ptr::write_volatile(0xdeadbeaf000 as *mut u64, 42) };
+}
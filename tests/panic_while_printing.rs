@@ -0,0 +1,36 @@
+#![no_std]
+#![no_main]
+
+use rudos::{
+    exit_qemu, serial::SERIAL1, serial_print, serial_println, vga_buffer::WRITER, QemuExitCode,
+};
+
+#[no_mangle]
+pub extern "C" fn _start() -> ! {
+    serial_print!("panic_while_printing::panic_with_locks_held...\t");
+
+    // Like a panic in the middle of a print, the guards are never dropped
+    core::mem::forget(WRITER.lock());
+    core::mem::forget(SERIAL1.lock());
+    panic!("panicked while printing");
+}
+
+#[panic_handler]
+fn panic(info: &core::panic::PanicInfo) -> ! {
+    if !rudos::begin_panic() {
+        exit_qemu(QemuExitCode::Failed);
+        rudos::hlt_loop();
+    }
+
+    // Both would deadlock if the locks were still held
+    rudos::println!("{info}");
+    if info.message().as_str() == Some("panicked while printing") {
+        serial_println!("[ok]");
+        exit_qemu(QemuExitCode::Success);
+    } else {
+        serial_println!("[failed]\n");
+        serial_println!("Error: {}\n", info);
+        exit_qemu(QemuExitCode::Failed);
+    }
+    rudos::hlt_loop();
+}
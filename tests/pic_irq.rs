@@ -0,0 +1,84 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(rudos::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use rudos::interrupts::{self, irq_vector, IRQ_LINES, PIC_1_OFFSET};
+use rudos::rtc::{read_cmos, write_cmos, RTC_IRQ};
+use x86_64::instructions::interrupts::without_interrupts;
+
+#[no_mangle]
+pub extern "C" fn _start() -> ! {
+    rudos::init();
+    test_main();
+
+    rudos::hlt_loop();
+}
+
+#[panic_handler]
+fn panic(info: &core::panic::PanicInfo) -> ! {
+    rudos::test_panic_handler(info)
+}
+
+#[test_case]
+fn vectors_are_distinct() {
+    for line in 0..IRQ_LINES {
+        assert_eq!(irq_vector(line), PIC_1_OFFSET + line);
+    }
+}
+
+static RTC_FIRED: AtomicBool = AtomicBool::new(false);
+
+fn rtc_handler() {
+    // The RTC doesn't raise another interrupt until register C is read
+    let _ = read_cmos(0x0C);
+    RTC_FIRED.store(true, Ordering::SeqCst);
+}
+
+/// Toggles the periodic interrupt bit in RTC register B
+fn set_rtc_periodic_interrupt(enabled: bool) {
+    without_interrupts(|| {
+        let register_b = read_cmos(0x0B);
+        let register_b = if enabled {
+            register_b | 0x40
+        } else {
+            register_b & !0x40
+        };
+        write_cmos(0x0B, register_b);
+        let _ = read_cmos(0x0C);
+    });
+}
+
+#[test_case]
+fn rtc_irq_is_dispatched_from_secondary_pic() {
+    interrupts::register_irq(RTC_IRQ, rtc_handler).expect("Registering the RTC handler failed");
+    set_rtc_periodic_interrupt(true);
+
+    while !RTC_FIRED.load(Ordering::SeqCst) {
+        x86_64::instructions::hlt();
+    }
+
+    set_rtc_periodic_interrupt(false);
+    interrupts::unregister_irq(RTC_IRQ, rtc_handler).expect("Unregistering the RTC handler failed");
+}
+
+static PARALLEL_PORT_CALLS: AtomicUsize = AtomicUsize::new(0);
+
+fn parallel_port_handler() {
+    PARALLEL_PORT_CALLS.fetch_add(1, Ordering::SeqCst);
+}
+
+#[test_case]
+fn spurious_irq_is_ignored() {
+    let spurious_before = interrupts::spurious_irqs();
+    interrupts::register_irq(7, parallel_port_handler).expect("Registering failed");
+
+    // The PIC has nothing in service, so this looks exactly like a spurious IRQ 7
+    unsafe { core::arch::asm!("int {vector}", vector = const PIC_1_OFFSET + 7) };
+
+    interrupts::unregister_irq(7, parallel_port_handler).expect("Unregistering failed");
+    assert_eq!(PARALLEL_PORT_CALLS.load(Ordering::SeqCst), 0);
+    assert_eq!(interrupts::spurious_irqs(), spurious_before + 1);
+}
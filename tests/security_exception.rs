@@ -0,0 +1,13 @@
+#![no_std]
+#![no_main]
+
+use core::arch::asm;
+
+rudos::exception_test!("SECURITY EXCEPTION", trigger);
+
+/// Only SVM raises it when entering the guest, so we raise the vector directly
+///
+/// `int` pushes no error code, so the reported one is garbage.
+fn trigger() {
+    unsafe { asm!("int 30") };
+}
@@ -0,0 +1,11 @@
+#![no_std]
+#![no_main]
+
+use core::arch::asm;
+
+rudos::exception_test!("SEGMENT NOT PRESENT", trigger);
+
+/// Interrupts through a vector without a handler, its gate isn't present
+fn trigger() {
+    unsafe { asm!("int 0x80") };
+}
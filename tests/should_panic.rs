@@ -8,14 +8,14 @@ pub extern "C" fn _start() -> ! {
     should_fail();
     serial_println!("[test did not panic]");
     exit_qemu(QemuExitCode::Failed);
-    loop {}
+    rudos::hlt_loop();
 }
 
 #[panic_handler]
 fn panic(_info: &core::panic::PanicInfo) -> ! {
     serial_println!("[ok]\n");
     exit_qemu(QemuExitCode::Success);
-    loop {}
+    rudos::hlt_loop();
 }
 
 fn should_fail() {
@@ -0,0 +1,32 @@
+#![no_std]
+#![no_main]
+
+use core::arch::asm;
+
+rudos::exception_test!("SIMD FLOATING POINT", trigger);
+
+/// Divides one by zero with the SSE divide by zero exception unmasked
+fn trigger() {
+    use x86_64::registers::control::{Cr0, Cr0Flags, Cr4, Cr4Flags};
+
+    // The default MXCSR with the divide by zero exception unmasked
+    let mxcsr: u32 = 0x1d80;
+    let one: f32 = 1.0;
+    unsafe {
+        // SSE is disabled for the kernel, it's enabled here with its exceptions
+        Cr0::update(|flags| {
+            flags.remove(Cr0Flags::EMULATE_COPROCESSOR | Cr0Flags::TASK_SWITCHED);
+            flags.insert(Cr0Flags::MONITOR_COPROCESSOR);
+        });
+        Cr4::update(|flags| flags.insert(Cr4Flags::OSFXSR | Cr4Flags::OSXMMEXCPT_ENABLE));
+        // Nothing else uses the SSE registers
+        asm!(
+            "ldmxcsr [{}]",
+            "movss xmm0, [{}]",
+            "xorps xmm1, xmm1",
+            "divss xmm0, xmm1",
+            in(reg) &mxcsr,
+            in(reg) &one,
+        );
+    }
+}
@@ -0,0 +1,37 @@
+#![no_std]
+#![no_main]
+
+use core::arch::asm;
+
+rudos::exception_test!("STACK SEGMENT FAULT", trigger);
+
+/// A writable data segment of privilege level 0 which isn't present
+const NOT_PRESENT_DATA_SEGMENT: u64 = 0x12 << 40;
+
+/// The entries of the kernel GDT followed by `NOT_PRESENT_DATA_SEGMENT`
+static mut GDT: [u64; 16] = [0; 16];
+
+/// Loads the stack segment register with a segment that isn't present
+fn trigger() {
+    use x86_64::instructions::tables::{lgdt, sgdt};
+    use x86_64::structures::DescriptorTablePointer;
+    use x86_64::VirtAddr;
+
+    let kernel = sgdt();
+    let entries = (usize::from(kernel.limit) + 1) / 8;
+    let gdt = unsafe { &mut *core::ptr::addr_of_mut!(GDT) };
+    assert!(entries < gdt.len(), "the kernel GDT is too big");
+    // The segments in use keep their selectors
+    gdt[..entries]
+        .copy_from_slice(unsafe { core::slice::from_raw_parts(kernel.base.as_ptr(), entries) });
+    gdt[entries] = NOT_PRESENT_DATA_SEGMENT;
+    let selector = (entries * 8) as u16;
+
+    unsafe {
+        lgdt(&DescriptorTablePointer {
+            limit: ((entries + 1) * 8 - 1) as u16,
+            base: VirtAddr::from_ptr(gdt.as_ptr()),
+        });
+        asm!("mov ss, {0:x}", in(reg) selector);
+    }
+}
@@ -0,0 +1,110 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(rudos::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+use bootloader::{entry_point, BootInfo};
+use core::sync::atomic::{AtomicBool, Ordering};
+use rudos::time::{Duration, Instant};
+use rudos::{serial_print, thread};
+use spin::Mutex;
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    use rudos::memory::{self, BitmapFrameAllocator};
+    use x86_64::VirtAddr;
+
+    rudos::init();
+
+    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    let mut mapper = unsafe { memory::init(phys_mem_offset) };
+    let mut frame_allocator =
+        unsafe { BitmapFrameAllocator::init(&boot_info.memory_map, phys_mem_offset) };
+    memory::heap::init(&mut mapper, &mut frame_allocator).expect("Heap initialization failed");
+    thread::init();
+
+    test_main();
+    rudos::hlt_loop();
+}
+
+#[panic_handler]
+fn panic(info: &core::panic::PanicInfo) -> ! {
+    rudos::test_panic_handler(info)
+}
+
+#[test_case]
+fn join_returns_result() {
+    let handle = thread::spawn(|| 6 * 7);
+    assert_eq!(handle.join(), 42);
+}
+
+#[test_case]
+fn preemption_interleaves_threads() {
+    static A_STARTED: AtomicBool = AtomicBool::new(false);
+    static B_STARTED: AtomicBool = AtomicBool::new(false);
+
+    // Neither thread yields, each can only see the other start if the timer switches
+    let a = thread::spawn(|| {
+        serial_print!("a");
+        A_STARTED.store(true, Ordering::SeqCst);
+        while !B_STARTED.load(Ordering::SeqCst) {
+            core::hint::spin_loop();
+        }
+        serial_print!("a ");
+    });
+    let b = thread::spawn(|| {
+        serial_print!("b");
+        B_STARTED.store(true, Ordering::SeqCst);
+        while !A_STARTED.load(Ordering::SeqCst) {
+            core::hint::spin_loop();
+        }
+        serial_print!("b ");
+    });
+
+    a.join();
+    b.join();
+}
+
+#[test_case]
+fn yield_now_alternates_threads() {
+    static LOG: Mutex<Vec<char>> = Mutex::new(Vec::new());
+
+    fn worker(name: char) {
+        for _ in 0..3 {
+            serial_print!("{name}");
+            LOG.lock().push(name);
+            thread::yield_now();
+        }
+    }
+
+    let a = thread::spawn(|| worker('a'));
+    let b = thread::spawn(|| worker('b'));
+    a.join();
+    b.join();
+    serial_print!(" ");
+
+    let log = LOG.lock();
+    assert_eq!(log.len(), 6);
+    assert_ne!(log.as_slice(), ['a', 'a', 'a', 'b', 'b', 'b']);
+}
+
+#[test_case]
+fn sleep_waits_for_duration() {
+    let start = Instant::now();
+    thread::sleep(Duration::from_millis(20));
+    assert!(start.elapsed() >= Duration::from_millis(20));
+}
+
+#[test_case]
+fn current_thread_changes() {
+    let main_thread = thread::current().expect("The scheduler isn't initialized");
+    let handle = thread::spawn(thread::current);
+    let spawned = handle.id();
+    assert_eq!(handle.join(), Some(spawned));
+    assert_ne!(main_thread, spawned);
+}
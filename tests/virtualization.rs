@@ -0,0 +1,11 @@
+#![no_std]
+#![no_main]
+
+use core::arch::asm;
+
+rudos::exception_test!("VIRTUALIZATION", trigger);
+
+/// We don't run under EPT, so we raise the vector directly
+fn trigger() {
+    unsafe { asm!("int 20") };
+}
@@ -0,0 +1,13 @@
+#![no_std]
+#![no_main]
+
+use core::arch::asm;
+
+rudos::exception_test!("VMM COMMUNICATION EXCEPTION", trigger);
+
+/// Only SEV-ES guests get it from the hardware, so we raise the vector directly
+///
+/// `int` pushes no error code, so the reported one is garbage.
+fn trigger() {
+    unsafe { asm!("int 29") };
+}
@@ -0,0 +1,30 @@
+#![no_std]
+#![no_main]
+
+use core::arch::asm;
+
+rudos::exception_test!("X87 FLOATING POINT", trigger);
+
+/// Divides zero by zero with the x87 exceptions unmasked, `fwait` raises the pending one
+fn trigger() {
+    use x86_64::registers::control::{Cr0, Cr0Flags};
+
+    // Every exception unmasked, 64 bit precision and rounding to nearest
+    let control_word: u16 = 0x0340;
+    unsafe {
+        // Reported as an exception instead of the legacy FERR# interrupt
+        Cr0::update(|flags| {
+            flags.insert(Cr0Flags::NUMERIC_ERROR);
+            flags.remove(Cr0Flags::EMULATE_COPROCESSOR | Cr0Flags::TASK_SWITCHED);
+        });
+        asm!(
+            "fninit",
+            "fldcw [{}]",
+            "fldz",
+            "fldz",
+            "fdivp",
+            "fwait",
+            in(reg) &control_word,
+        );
+    }
+}